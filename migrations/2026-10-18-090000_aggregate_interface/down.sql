@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE interfaces DROP COLUMN aggregate_interface;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE interfaces ADD COLUMN aggregate_interface int REFERENCES interfaces(id) DEFAULT NULL;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE topology_snapshots (
+  id serial PRIMARY KEY,
+  created_at double precision NOT NULL,
+  reason varchar NOT NULL,
+  data text NOT NULL
+);
\ No newline at end of file
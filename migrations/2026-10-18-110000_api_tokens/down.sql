@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE api_token_usages;
+DROP TABLE api_tokens;
\ No newline at end of file
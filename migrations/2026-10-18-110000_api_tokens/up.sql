@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE api_tokens (
+  id serial PRIMARY KEY,
+  name varchar NOT NULL,
+  token_hash varchar NOT NULL UNIQUE,
+  scopes varchar NOT NULL,
+  created_at double precision NOT NULL,
+  revoked boolean NOT NULL DEFAULT false
+);
+
+CREATE TABLE api_token_usages (
+  id serial PRIMARY KEY,
+  token_id int REFERENCES api_tokens(id) DEFAULT NULL,
+  token_name varchar NOT NULL,
+  used_at double precision NOT NULL,
+  method varchar NOT NULL,
+  uri varchar NOT NULL,
+  status int NOT NULL
+);
+
+CREATE INDEX api_token_usages_token_id ON api_token_usages (token_id);
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+-- Communities stay encrypted, they cannot be decrypted without the nexus key
+ALTER TABLE devices ADD COLUMN snmp_community varchar DEFAULT NULL;
+UPDATE devices SET snmp_community = device_credentials.snmp_community
+  FROM device_credentials WHERE device_credentials.device_id = devices.id;
+
+DROP TABLE device_credentials;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE device_credentials (
+  id serial PRIMARY KEY,
+  device_id int NOT NULL UNIQUE REFERENCES devices(id),
+  snmp_community varchar DEFAULT NULL
+);
+
+-- Copied as plaintext, nexus encrypts these on startup
+INSERT INTO device_credentials (device_id, snmp_community)
+  SELECT id, snmp_community FROM devices WHERE snmp_community IS NOT NULL;
+
+ALTER TABLE devices DROP COLUMN snmp_community;
\ No newline at end of file
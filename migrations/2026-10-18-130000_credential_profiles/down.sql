@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX devices_credential_profile_id_idx;
+ALTER TABLE devices DROP COLUMN credential_profile_id;
+DROP TABLE credential_profiles;
\ No newline at end of file
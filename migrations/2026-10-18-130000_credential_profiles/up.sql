@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Community and passwords are stored encrypted by nexus
+CREATE TABLE credential_profiles (
+  id serial PRIMARY KEY,
+  name varchar NOT NULL UNIQUE,
+  snmp_version varchar NOT NULL,
+  community varchar DEFAULT NULL,
+  username varchar DEFAULT NULL,
+  auth_protocol varchar DEFAULT NULL,
+  auth_password varchar DEFAULT NULL,
+  priv_protocol varchar DEFAULT NULL,
+  priv_password varchar DEFAULT NULL
+);
+
+ALTER TABLE devices ADD COLUMN credential_profile_id int DEFAULT NULL REFERENCES credential_profiles(id);
+CREATE INDEX devices_credential_profile_id_idx ON devices (credential_profile_id);
\ No newline at end of file
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE audit_entries (
+  id serial PRIMARY KEY,
+  created_at double precision NOT NULL,
+  token_id int DEFAULT NULL REFERENCES api_tokens(id),
+  token_name varchar NOT NULL,
+  entity_type varchar NOT NULL,
+  entity_key varchar NOT NULL,
+  action varchar NOT NULL,
+  before_data text DEFAULT NULL,
+  after_data text DEFAULT NULL
+);
+
+CREATE INDEX audit_entries_entity ON audit_entries (entity_type, entity_key);
+CREATE INDEX audit_entries_created_at ON audit_entries (created_at);
\ No newline at end of file
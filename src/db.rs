@@ -2,25 +2,22 @@ use std::ops::Deref;
 use rocket::http::Status;
 use rocket::request::{self, FromRequest};
 use rocket::{Request, State, Outcome};
-use std::env;
 use r2d2;
+use utilities;
 use r2d2_diesel::ConnectionManager;
 
+use diesel;
+use diesel::Connection as DieselConnection;
 use diesel::pg::PgConnection;
 
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
-pub fn connect() -> Pool {
-    let env_opt = env::var("DATABASE_URL");
-    match env_opt {
-        Ok(env_opt) => {
-            let manager = ConnectionManager::<PgConnection>::new(env_opt);
-            r2d2::Pool::builder().build(manager).expect("Failed to create pool")
-        },
-        Err(_) => {
-            panic!("DATABASE_URL env var not set!");
-        }
-    }
+pub fn connect(config: &utilities::config::Config) -> Pool {
+    let manager = ConnectionManager::<PgConnection>::new(config.database_url());
+    r2d2::Pool::builder()
+        .max_size(config.database.pool_size)
+        .build(manager)
+        .expect("Failed to create pool")
 }
 
 pub struct Connection(pub r2d2::PooledConnection<ConnectionManager<PgConnection>>);
@@ -43,4 +40,28 @@ impl Deref for Connection {
     fn deref(&self) -> &Self::Target {
         &self.0
     }
+}
+
+// Runs ingest in a transaction. With rollback its changes are discarded, but a successful result
+// is still returned so that callers can report what would have changed.
+pub fn transaction<T, F>(connection: &PgConnection, rollback: bool, ingest: F) -> Result<T, utilities::errors::ApiError>
+    where F: FnOnce() -> Result<T, utilities::errors::ApiError>
+{
+    let mut rolled_back_result : Option<T> = None;
+    let result = connection.transaction::<T, utilities::errors::ApiError, _>(|| {
+        let ingest_result = ingest()?;
+        if rollback {
+            rolled_back_result = Some(ingest_result);
+            return Err(utilities::errors::ApiError::Database(diesel::result::Error::RollbackTransaction));
+        }
+        return Ok(ingest_result);
+    });
+    match rolled_back_result {
+        Some(rolled_back_result) => {
+            return Ok(rolled_back_result);
+        },
+        None => {
+            return result;
+        }
+    }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@
 #![feature(decl_macro)]
 #![feature(proc_macro_hygiene)]
 #![allow(proc_macro_derive_resolution_fallback)] // remove when able
+extern crate serde;
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate serde_json;
 #[macro_use] extern crate diesel;
@@ -12,11 +13,19 @@ extern crate r2d2;
 extern crate r2d2_diesel;
 extern crate time;
 extern crate zmq;
+extern crate toml;
+extern crate rand;
+extern crate sha2;
+extern crate chacha20poly1305;
+extern crate base64;
+extern crate serde_yaml;
+extern crate csv;
+// Declared first so the logging macros are visible in every other module
+#[macro_use] mod utilities;
 mod routes;
 mod models;
 mod db;
 mod schema;
-mod utilities;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::AtomicBool;
 use std::collections::HashMap;
@@ -25,47 +34,72 @@ fn should_continue(running : &std::sync::atomic::AtomicBool) -> bool {
     return running.load(std::sync::atomic::Ordering::Relaxed);
 }
 
-fn imds_worker(running : Arc<AtomicBool>, imds : Arc<Mutex<utilities::imds::IMDS>>, metric_miss_cache: Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>) {
+fn imds_worker(running : Arc<AtomicBool>, imds : Arc<Mutex<utilities::imds::IMDS>>, metric_miss_cache: Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>, cache_controller: Arc<Mutex<utilities::cache::CacheController>>, self_metrics: Arc<Mutex<utilities::selfmetrics::SelfMetrics>>, runtime_info: Arc<Mutex<models::internal::RuntimeInfo>>, config: utilities::config::Config) {
     let mut first_run_done = false;
-    let mut refresh_run_counter : i32 = 0;
-    let pool = db::connect();
+    let mut refresh_run_counter : u32 = 0;
+    let worker_tick = std::time::Duration::from_millis((config.worker.tick * 1000.0) as u64);
+    let pool = db::connect(&config);
     loop {
         if !should_continue(&running) { break; }
+        let iteration_started = utilities::tools::get_time();
         let mut refresh = false;
         let mut refresh_devices : Vec<models::dbo::Device> = Vec::new();
         let mut refresh_interfaces : HashMap<String, Vec<models::dbo::Interface>> = HashMap::new();
+        let mut topology_graph : Option<Arc<utilities::topology::TopologyGraph>> = None;
         {
             if let Ok(ref mut metric_miss_cache) = metric_miss_cache.lock() {
                 match pool.get() {
                     Ok(conn) => {
+                        // Cached, so this only reaches the database after topology changes or TTL expiry
+                        if let Ok(ref cache_controller) = cache_controller.lock() {
+                            topology_graph = Some(cache_controller.topology_graph(&conn));
+                        }
                         if !first_run_done || !metric_miss_cache.miss_set.is_empty() || refresh_run_counter == 0 {
                             refresh = true;
                             for device in models::dbo::Device::monitored(&conn).iter() {
                                 let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
                                 if refresh_run_counter == 0 || metric_miss_cache.miss_set.contains(&device_fqdn) {
                                     refresh_devices.push(device.clone());
-                                    refresh_interfaces.insert(device_fqdn, device.interfaces(&conn));
                                 }
                             }
+                            let grouped_interfaces = models::dbo::Device::interfaces_grouped(&refresh_devices, &conn);
+                            for (device, device_interfaces) in refresh_devices.iter().zip(grouped_interfaces.into_iter()) {
+                                refresh_interfaces.insert(format!("{}.{}", device.name, device.dns_domain), device_interfaces);
+                            }
                         }
                         metric_miss_cache.miss_set.clear();
                     },
-                    Err(_) => {
-                        // TODO: log?
-                        std::thread::sleep(std::time::Duration::from_millis(1000));
+                    Err(error) => {
+                        log_error!("imds_worker could not get a database connection", "error" => error);
+                        std::thread::sleep(worker_tick);
                         continue;
                     }
                 }
             }
         }
         {
+            let lock_started = utilities::tools::get_time();
             if let Ok(ref mut imds) = imds.lock() {
+                if let Ok(ref mut self_metrics) = self_metrics.lock() {
+                    self_metrics.record_imds_lock_wait(utilities::tools::get_time() - lock_started);
+                }
+                if let Some(topology_graph) = topology_graph {
+                    imds.set_topology(topology_graph);
+                }
                 for device in refresh_devices.iter() {
                     let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
                     imds.refresh_device(&device_fqdn);
                     if let Some(device_interfaces) = refresh_interfaces.get(&device_fqdn) {
+                        let mut interface_indexes_by_id : HashMap<i32, i32> = HashMap::new();
                         for interface in device_interfaces.iter() {
-                            imds.refresh_interface(&device_fqdn, interface.index, &interface.interface_type, &interface.name(), interface.connected_interface.is_some() || interface.virtual_connection.is_some(), interface.speed_override);
+                            interface_indexes_by_id.insert(interface.id, interface.index);
+                        }
+                        for interface in device_interfaces.iter() {
+                            let aggregate_index = match interface.aggregate_interface {
+                                Some(aggregate_interface_id) => interface_indexes_by_id.get(&aggregate_interface_id).cloned(),
+                                None => None,
+                            };
+                            imds.refresh_interface(&device_fqdn, interface.index, &interface.interface_type, &interface.name(), interface.connected_interface.is_some() || interface.virtual_connection.is_some(), interface.speed_override, aggregate_index);
                         }
                     }
                 }
@@ -74,31 +108,138 @@ fn imds_worker(running : Arc<AtomicBool>, imds : Arc<Mutex<utilities::imds::IMDS
                 }
             };
         }
+        if let Ok(ref mut self_metrics) = self_metrics.lock() {
+            self_metrics.record_worker_iteration(utilities::tools::get_time() - iteration_started);
+        }
         first_run_done = true;
-        if refresh_run_counter >= 9 { refresh_run_counter = 0; } else { refresh_run_counter += 1; }
+        if let Ok(ref mut runtime_info) = runtime_info.lock() {
+            runtime_info.imds_worker_first_run_done = true;
+            runtime_info.imds_worker_last_iteration = Some(utilities::tools::get_time());
+        }
+        if refresh_run_counter + 1 >= config.worker.full_refresh_iterations { refresh_run_counter = 0; } else { refresh_run_counter += 1; }
+        std::thread::sleep(worker_tick);
+    }
+}
+
+// Shares the server pool, it only needs a connection for a moment every snapshot_interval
+fn snapshot_worker(running : Arc<AtomicBool>, pool: db::Pool, snapshot_interval: f64) {
+    if snapshot_interval <= 0.0 { return; }
+    let mut next_snapshot = utilities::tools::get_time();
+    loop {
+        if !should_continue(&running) { break; }
+        if utilities::tools::get_time() >= next_snapshot {
+            match pool.get() {
+                Ok(conn) => {
+                    match utilities::topology::take_snapshot(&conn, "periodic", true) {
+                        Ok(Some(topology_snapshot)) => {
+                            log_info!("periodic topology snapshot taken", "snapshot_id" => topology_snapshot.id);
+                        },
+                        Ok(None) => {},
+                        Err(error) => {
+                            log_error!("failed to store periodic topology snapshot", "error" => error);
+                        }
+                    }
+                    next_snapshot = utilities::tools::get_time() + snapshot_interval;
+                },
+                Err(error) => {
+                    log_error!("snapshot_worker could not get a database connection", "error" => error);
+                }
+            }
+        }
         std::thread::sleep(std::time::Duration::from_millis(1000));
     }
 }
 
+fn rocket_config(config: &utilities::config::Config) -> rocket::config::Config {
+    let environment = rocket::config::Environment::active().unwrap_or(rocket::config::Environment::Development);
+    let mut rocket_config_builder = rocket::config::Config::build(environment)
+        .address(config.http.address.clone())
+        .port(config.http.port);
+    if let Some(workers) = config.http.workers {
+        rocket_config_builder = rocket_config_builder.workers(workers);
+    }
+    match rocket_config_builder.finalize() {
+        Ok(rocket_config) => {
+            return rocket_config;
+        },
+        Err(error) => {
+            eprintln!("Invalid configuration: http: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() {
+    let config = match utilities::config::Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Invalid configuration: {}", error);
+            std::process::exit(1);
+        }
+    };
+    utilities::logging::init(&config.logging);
+    let credential_cipher = match utilities::credentials::CredentialCipher::new(&config.credentials_key()) {
+        Ok(credential_cipher) => credential_cipher,
+        Err(error) => {
+            eprintln!("Invalid configuration: credentials.key: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(utilities::backup::run_command(&args, &config, &credential_cipher));
+    }
     let running = Arc::new(AtomicBool::new(true));
     
-    let msgbus : Arc<Mutex<utilities::msgbus::MessageBus>> = Arc::new(Mutex::new(utilities::msgbus::MessageBus::new()));
-    let imds : Arc<Mutex<utilities::imds::IMDS>> = Arc::new(Mutex::new(utilities::imds::IMDS::new(msgbus.clone())));
+    let msgbus : Arc<Mutex<utilities::msgbus::MessageBus>> = Arc::new(Mutex::new(utilities::msgbus::MessageBus::new(config.event_publish())));
+    let imds : Arc<Mutex<utilities::imds::IMDS>> = Arc::new(Mutex::new(utilities::imds::IMDS::new(msgbus.clone(), config.imds.expiry)));
     let metric_miss_cache : Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>> = Arc::new(Mutex::new(models::metrics::DeviceMetricRefreshCacheMiss::new()));
 
+    let cache_controller : Arc<Mutex<utilities::cache::CacheController>> = Arc::new(Mutex::new(utilities::cache::CacheController::new(config.cache.topology_ttl)));
+    let self_metrics : Arc<Mutex<utilities::selfmetrics::SelfMetrics>> = Arc::new(Mutex::new(utilities::selfmetrics::SelfMetrics::new()));
+
+    let token_cache : Arc<Mutex<utilities::auth::TokenCache>> = Arc::new(Mutex::new(utilities::auth::TokenCache::new()));
+
+    let runtime_info : Arc<Mutex<models::internal::RuntimeInfo>> = Arc::new(Mutex::new(models::internal::RuntimeInfo::new()));
+
     let imds_worker_imds = imds.clone();
     let imds_worker_running = running.clone();
     let imds_worder_metric_miss_cache = metric_miss_cache.clone();
+    let imds_worker_cache_controller = cache_controller.clone();
+    let imds_worker_self_metrics = self_metrics.clone();
+    let imds_worker_runtime_info = runtime_info.clone();
+    let imds_worker_config = config.clone();
     let imds_worker_thread = std::thread::spawn(|| {
-        imds_worker(imds_worker_running, imds_worker_imds, imds_worder_metric_miss_cache);
+        imds_worker(imds_worker_running, imds_worker_imds, imds_worder_metric_miss_cache, imds_worker_cache_controller, imds_worker_self_metrics, imds_worker_runtime_info, imds_worker_config);
     });
 
-    let cache_controller : Arc<Mutex<utilities::cache::CacheController>> = Arc::new(Mutex::new(utilities::cache::CacheController::new()));
+    let pool = db::connect(&config);
 
-    let runtime_info : Arc<Mutex<models::internal::RuntimeInfo>> = Arc::new(Mutex::new(models::internal::RuntimeInfo::new()));
-    
-    rocket::ignite()
+    let snapshot_worker_running = running.clone();
+    let snapshot_worker_pool = pool.clone();
+    let snapshot_worker_interval = config.topology.snapshot_interval;
+    let snapshot_worker_thread = std::thread::spawn(move || {
+        snapshot_worker(snapshot_worker_running, snapshot_worker_pool, snapshot_worker_interval);
+    });
+    match pool.get() {
+        Ok(conn) => {
+            utilities::credentials::encrypt_legacy_credentials(&credential_cipher, &conn);
+        },
+        Err(error) => {
+            log_error!("could not get a database connection to encrypt stored credentials", "error" => error);
+        }
+    }
+
+    rocket::custom(rocket_config(&config))
+        .attach(utilities::selfmetrics::RequestMetricsFairing { self_metrics: self_metrics.clone() })
+        .attach(utilities::logging::RequestLoggingFairing)
+        .attach(utilities::auth::TokenAuditFairing)
+        .mount(
+            "/audit",
+            routes![
+                routes::audit::audit_list,
+            ]
+        )
         .mount(
             "/clientlocation",
             routes![
@@ -112,14 +253,37 @@ fn main() {
                 routes::discovery::discovery_links,
             ]
         )
+        .mount(
+            "/credentialprofile",
+            routes![
+                routes::credentialprofile::credential_profile_list,
+                routes::credentialprofile::credential_profile_create_or_modify,
+                routes::credentialprofile::credential_profile_delete,
+            ]
+        )
         .mount(
             "/device",
             routes![
                 routes::device::device_list,
                 routes::device::device_create_or_modify,
                 routes::device::device_delete,
+                routes::device::device_get,
+                routes::device::device_get_by_fqdn,
+                routes::device::device_patch,
+                routes::device::device_patch_by_fqdn,
+                routes::device::device_delete_by_id,
+                routes::device::device_delete_by_fqdn,
+                routes::device::device_credentials,
                 routes::device::monitored_device_list,
                 routes::device::monitored_device_report,
+                routes::device::monitored_device_report_batch,
+            ]
+        )
+        .mount(
+            "/health",
+            routes![
+                routes::health::health_live,
+                routes::health::health_ready,
             ]
         )
         .mount(
@@ -127,6 +291,14 @@ fn main() {
             routes![
                 routes::interface::interface_list,
                 routes::interface::interface_monitor_report,
+                routes::interface::interface_monitor_report_batch,
+            ]
+        )
+        .mount(
+            "/inventory",
+            routes![
+                routes::inventory::inventory_export,
+                routes::inventory::inventory_import,
             ]
         )
         .mount(
@@ -134,6 +306,29 @@ fn main() {
             routes![
                 routes::metrics::metrics_fast,
                 routes::metrics::metrics,
+                routes::metrics::metrics_self,
+            ]
+        )
+        .mount(
+            "/token",
+            routes![
+                routes::token::token_list,
+                routes::token::token_create,
+                routes::token::token_revoke,
+                routes::token::token_usage,
+            ]
+        )
+        .mount(
+            "/topology",
+            routes![
+                routes::topology::topology_path,
+                routes::topology::topology_spof,
+                routes::topology::topology_impact,
+                routes::topology::topology_single_uplink,
+                routes::topology::topology_snapshot_list,
+                routes::topology::topology_snapshot_create,
+                routes::topology::topology_snapshot_data,
+                routes::topology::topology_snapshot_diff,
             ]
         )
         .mount(
@@ -145,14 +340,31 @@ fn main() {
                 routes::weathermap::put_position_data,
             ]
         )
-        .manage(db::connect())
+        .register(
+            catchers![
+                routes::catchers::bad_request,
+                routes::catchers::unauthorized,
+                routes::catchers::forbidden,
+                routes::catchers::not_found,
+                routes::catchers::unprocessable_entity,
+                routes::catchers::internal_error,
+                routes::catchers::service_unavailable,
+            ]
+        )
+        .manage(pool)
         .manage(imds.clone())
         .manage(metric_miss_cache.clone())
         .manage(cache_controller.clone())
         .manage(runtime_info.clone())
         .manage(msgbus.clone())
+        .manage(self_metrics.clone())
+        .manage(token_cache.clone())
+        .manage(utilities::topology::TopologyRoots::new(&config.topology.roots))
+        .manage(credential_cipher)
+        .manage(config.clone())
         .launch();
 
     (*running).store(false, std::sync::atomic::Ordering::Relaxed);
     imds_worker_thread.join().unwrap();
+    snapshot_worker_thread.join().unwrap();
 }
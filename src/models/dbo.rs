@@ -1,11 +1,59 @@
-use schema::{devices,interfaces,weathermap_device_infos,client_locations};
+use schema::{devices,device_credentials,credential_profiles,interfaces,weathermap_device_infos,client_locations,topology_snapshots,api_tokens,api_token_usages,audit_entries};
 use diesel;
-use diesel::pg::PgConnection;
+use diesel::pg::{Pg, PgConnection};
 use diesel::BelongingToDsl;
+use diesel::GroupedBy;
 use diesel::RunQueryDsl;
 use diesel::QueryDsl;
 use diesel::ExpressionMethods;
 use diesel::BoolExpressionMethods;
+use diesel::TextExpressionMethods;
+use diesel::pg::expression::expression_methods::PgTextExpressionMethods;
+
+// Lookups treat a missing row as a normal outcome, anything else is worth knowing about
+fn log_query_error(query: &str, error: &diesel::result::Error) {
+    if let diesel::result::Error::NotFound = *error { return; }
+    log_error!("database query failed", "query" => query, "error" => error);
+}
+
+// LIKE patterns are built from user input, so its wildcards must match literally
+fn like_escape(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+}
+
+pub const DEVICE_SORT_FIELDS: [&str; 4] = ["id", "name", "domain", "osInfo"];
+pub const INTERFACE_SORT_FIELDS: [&str; 4] = ["id", "name", "index", "device"];
+
+// Keeps a bulk insert well below the 65535 bind parameter limit of postgres
+const RESTORE_CHUNK_SIZE: usize = 1000;
+
+// Tables covered by backups, in the order they have to be restored. Their load_all() queries fail
+// instead of returning an empty list like all(), a backup must not silently miss rows.
+pub const BACKUP_TABLES: [&str; 7] = ["credential_profiles", "devices", "device_credentials", "interfaces", "weathermap_device_infos", "client_locations", "topology_snapshots"];
+
+pub struct Pagination {
+    pub sort: String,
+    pub descending: bool,
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+pub struct DeviceFilter {
+    pub name_prefix: Option<String>,
+    pub dns_domain_prefix: Option<String>,
+    pub os_info_contains: Option<String>,
+    pub base_mac: Option<String>,
+    pub polling_enabled: Option<bool>,
+}
+
+pub struct InterfaceFilter {
+    pub device_id: Option<i32>,
+    pub name_prefix: Option<String>,
+    pub interface_type: Option<String>,
+    pub has_neighbor: Option<bool>,
+    // Searched in name, display name, alias and description
+    pub text: Option<String>,
+}
 
 #[table_name = "devices"]
 #[derive(Insertable, Serialize, Deserialize)]
@@ -13,10 +61,10 @@ use diesel::BoolExpressionMethods;
 pub struct NewDevice {
     pub name: String,
     pub dns_domain: String,
-    pub snmp_community: Option<String>,
     pub base_mac: Option<String>,
     pub polling_enabled: Option<bool>,
     pub os_info: Option<String>,
+    pub credential_profile_id: Option<i32>,
 }
 
 #[table_name = "interfaces"]
@@ -31,17 +79,66 @@ pub struct NewInterface {
 }
 
 #[table_name = "devices"]
-#[derive(Serialize, Deserialize, Queryable, Identifiable, AsChangeset, Clone)]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, AsChangeset, Clone)]
 #[serde(rename_all = "camelCase")]
 #[changeset_options(treat_none_as_null = "true")]
 pub struct Device {
     pub id: i32,
     pub name: String,
     pub dns_domain: String,
-    pub snmp_community: Option<String>,
     pub base_mac: Option<String>,
     pub polling_enabled: Option<bool>,
-    pub os_info: Option<String>
+    pub os_info: Option<String>,
+    pub credential_profile_id: Option<i32>,
+}
+
+#[table_name = "device_credentials"]
+#[derive(Insertable)]
+pub struct NewDeviceCredential {
+    pub device_id: i32,
+    pub snmp_community: Option<String>,
+}
+
+// Secrets are stored encrypted, see utilities::credentials
+#[belongs_to(Device)]
+#[table_name = "device_credentials"]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, AsChangeset, Associations, Clone)]
+#[serde(rename_all = "camelCase")]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct DeviceCredential {
+    pub id: i32,
+    pub device_id: i32,
+    pub snmp_community: Option<String>,
+}
+
+#[table_name = "credential_profiles"]
+#[derive(Insertable)]
+pub struct NewCredentialProfile {
+    pub name: String,
+    pub snmp_version: String,
+    pub community: Option<String>,
+    pub username: Option<String>,
+    pub auth_protocol: Option<String>,
+    pub auth_password: Option<String>,
+    pub priv_protocol: Option<String>,
+    pub priv_password: Option<String>,
+}
+
+// Community and passwords are stored encrypted, see utilities::credentials
+#[table_name = "credential_profiles"]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, AsChangeset, Clone)]
+#[serde(rename_all = "camelCase")]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CredentialProfile {
+    pub id: i32,
+    pub name: String,
+    pub snmp_version: String,
+    pub community: Option<String>,
+    pub username: Option<String>,
+    pub auth_protocol: Option<String>,
+    pub auth_password: Option<String>,
+    pub priv_protocol: Option<String>,
+    pub priv_password: Option<String>,
 }
 
 pub struct UpdatedWeathermapDeviceInfo {
@@ -69,9 +166,108 @@ pub struct NewClientLocation {
     pub port_info: String,
 }
 
+#[table_name = "topology_snapshots"]
+#[derive(Insertable)]
+pub struct NewTopologySnapshot {
+    pub created_at: f64,
+    pub reason: String,
+    pub data: String,
+}
+
+#[table_name = "topology_snapshots"]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySnapshot {
+    pub id: i32,
+    pub created_at: f64,
+    pub reason: String,
+    pub data: String,
+}
+
+// A snapshot without its data, listing them must not load every stored topology
+#[derive(Queryable)]
+pub struct TopologySnapshotSummary {
+    pub id: i32,
+    pub created_at: f64,
+    pub reason: String,
+}
+
+#[table_name = "api_tokens"]
+#[derive(Insertable)]
+pub struct NewApiToken {
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: String,
+    pub created_at: f64,
+    pub revoked: bool,
+}
+
+#[table_name = "api_tokens"]
+#[derive(Queryable, Identifiable, AsChangeset, Clone)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: String,
+    pub created_at: f64,
+    pub revoked: bool,
+}
+
+#[table_name = "api_token_usages"]
+#[derive(Insertable)]
+pub struct NewApiTokenUsage {
+    pub token_id: Option<i32>,
+    pub token_name: String,
+    pub used_at: f64,
+    pub method: String,
+    pub uri: String,
+    pub status: i32,
+}
+
+#[table_name = "api_token_usages"]
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenUsage {
+    pub id: i32,
+    pub token_id: Option<i32>,
+    pub token_name: String,
+    pub used_at: f64,
+    pub method: String,
+    pub uri: String,
+    pub status: i32,
+}
+
+#[table_name = "audit_entries"]
+#[derive(Insertable)]
+pub struct NewAuditEntry {
+    pub created_at: f64,
+    pub token_id: Option<i32>,
+    pub token_name: String,
+    pub entity_type: String,
+    pub entity_key: String,
+    pub action: String,
+    pub before_data: Option<String>,
+    pub after_data: Option<String>,
+}
+
+// before_data and after_data hold the serialized entity, None when it did not exist
+#[table_name = "audit_entries"]
+#[derive(Queryable, Identifiable, Clone)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub created_at: f64,
+    pub token_id: Option<i32>,
+    pub token_name: String,
+    pub entity_type: String,
+    pub entity_key: String,
+    pub action: String,
+    pub before_data: Option<String>,
+    pub after_data: Option<String>,
+}
+
 #[belongs_to(Device)]
 #[table_name = "client_locations"]
-#[derive(Serialize, Deserialize, Queryable, Identifiable, AsChangeset, Associations, Clone)]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, AsChangeset, Associations, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ClientLocation {
     pub id: i32,
@@ -82,7 +278,7 @@ pub struct ClientLocation {
 
 #[belongs_to(Device)]
 #[table_name = "weathermap_device_infos"]
-#[derive(Serialize, Deserialize, Queryable, Identifiable, AsChangeset, Associations, Clone)]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, AsChangeset, Associations, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct WeathermapDeviceInfo {
     pub id: i32,
@@ -95,7 +291,7 @@ pub struct WeathermapDeviceInfo {
 
 #[belongs_to(Device)]
 #[table_name = "interfaces"]
-#[derive(Serialize, Deserialize, Queryable, Identifiable, AsChangeset, Associations, Clone)]
+#[derive(Serialize, Deserialize, Queryable, Insertable, Identifiable, AsChangeset, Associations, Clone)]
 #[serde(rename_all = "camelCase")]
 #[changeset_options(treat_none_as_null = "true")]
 pub struct Interface {
@@ -111,9 +307,70 @@ pub struct Interface {
     pub polling_enabled: Option<bool>,
     pub speed_override: Option<i32>,
     pub virtual_connection: Option<i32>,
+    pub aggregate_interface: Option<i32>,
+}
+
+#[derive(QueryableByName)]
+struct SchemaMigration {
+    #[sql_type = "diesel::sql_types::Text"]
+    version: String,
+}
+
+// Version of the newest applied migration
+pub fn schema_version(connection: &PgConnection) -> Result<Option<String>, diesel::result::Error> {
+    let schema_migrations = diesel::sql_query("SELECT version FROM __diesel_schema_migrations ORDER BY version DESC LIMIT 1")
+        .load::<SchemaMigration>(connection)?;
+    return Ok(schema_migrations.into_iter().next().map(|schema_migration| schema_migration.version));
+}
+
+pub fn backup_tables_empty(connection: &PgConnection) -> Result<bool, diesel::result::Error> {
+    let row_count : i64 = credential_profiles::table.count().get_result::<i64>(connection)?
+        + devices::table.count().get_result::<i64>(connection)?
+        + device_credentials::table.count().get_result::<i64>(connection)?
+        + interfaces::table.count().get_result::<i64>(connection)?
+        + weathermap_device_infos::table.count().get_result::<i64>(connection)?
+        + client_locations::table.count().get_result::<i64>(connection)?
+        + topology_snapshots::table.count().get_result::<i64>(connection)?;
+    return Ok(row_count == 0);
+}
+
+// Restored rows keep their ids, so the sequences have to continue after them
+pub fn reset_backup_sequences(connection: &PgConnection) -> Result<(), diesel::result::Error> {
+    for table in BACKUP_TABLES.iter() {
+        diesel::sql_query(format!("SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE((SELECT MAX(id) FROM {0}), 0) + 1, false)", table))
+            .execute(connection)?;
+    }
+    return Ok(());
 }
 
 impl ClientLocation {
+    pub fn all(connection: &PgConnection) -> Vec<ClientLocation> {
+        match client_locations::table
+            .order(client_locations::id.asc())
+            .load(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("ClientLocation::all", &error);
+                return Vec::new();
+            }
+        }
+    }
+
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<ClientLocation>, diesel::result::Error> {
+        return client_locations::table.order(client_locations::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_client_locations: &Vec<ClientLocation>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_client_locations.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(client_locations::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+
+
     pub fn create(new_client_location: &NewClientLocation, connection: &PgConnection) -> Result<ClientLocation, diesel::result::Error> {
         let result = diesel::insert_into(client_locations::table)
             .values(new_client_location)
@@ -132,7 +389,8 @@ impl ClientLocation {
             Ok(client_location) => {
                 return Some(client_location);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("ClientLocation::by_ip", &error);
                 return None;
             }
         }
@@ -148,7 +406,8 @@ impl Device {
             Ok(device) => {
                 return Some(device);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::by_id", &error);
                 return None;
             }
         }
@@ -162,23 +421,88 @@ impl Device {
             Ok(device) => {
                 return Some(device);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::by_base_mac", &error);
                 return None;
             }
         }
     }
 
+    pub fn all_by_base_mac(base_mac: &String, connection: &PgConnection) -> Result<Vec<Device>, diesel::result::Error> {
+        return devices::table
+            .filter(devices::base_mac.eq(base_mac))
+            .order(devices::id)
+            .load::<Device>(connection);
+    }
+
     pub fn all(connection: &PgConnection) -> Vec<Device> {
         match devices::table.load(connection) {
             Ok(result) => {
                 return result;
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::all", &error);
                 return Vec::new();
             }
         }
     }
 
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<Device>, diesel::result::Error> {
+        return devices::table.order(devices::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_devices: &Vec<Device>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_devices.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(devices::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+
+    fn filtered<'a>(filter: &'a DeviceFilter) -> devices::BoxedQuery<'a, Pg> {
+        let mut query = devices::table.into_boxed();
+        if let Some(ref name_prefix) = filter.name_prefix {
+            query = query.filter(devices::name.like(format!("{}%", like_escape(name_prefix))));
+        }
+        if let Some(ref dns_domain_prefix) = filter.dns_domain_prefix {
+            query = query.filter(devices::dns_domain.like(format!("{}%", like_escape(dns_domain_prefix))));
+        }
+        if let Some(ref os_info_contains) = filter.os_info_contains {
+            query = query.filter(devices::os_info.ilike(format!("%{}%", like_escape(os_info_contains))));
+        }
+        if let Some(ref base_mac) = filter.base_mac {
+            query = query.filter(devices::base_mac.eq(base_mac));
+        }
+        match filter.polling_enabled {
+            // Same interpretation of NULL as Device::monitored
+            Some(true) => { query = query.filter(devices::polling_enabled.is_null().or(devices::polling_enabled.eq(true))); },
+            Some(false) => { query = query.filter(devices::polling_enabled.eq(false)); },
+            None => {},
+        }
+        return query;
+    }
+
+    // Returns the requested page and the number of devices matching the filter
+    pub fn search(filter: &DeviceFilter, pagination: &Pagination, connection: &PgConnection) -> Result<(Vec<Device>, i64), diesel::result::Error> {
+        let total : i64 = Device::filtered(filter).count().get_result(connection)?;
+        let mut query = Device::filtered(filter);
+        // id breaks ties so that pages do not overlap
+        query = match (pagination.sort.as_str(), pagination.descending) {
+            ("name", false) => query.order((devices::name.asc(), devices::dns_domain.asc(), devices::id.asc())),
+            ("name", true) => query.order((devices::name.desc(), devices::dns_domain.desc(), devices::id.desc())),
+            ("domain", false) => query.order((devices::dns_domain.asc(), devices::name.asc(), devices::id.asc())),
+            ("domain", true) => query.order((devices::dns_domain.desc(), devices::name.desc(), devices::id.desc())),
+            ("osInfo", false) => query.order((devices::os_info.asc(), devices::id.asc())),
+            ("osInfo", true) => query.order((devices::os_info.desc(), devices::id.desc())),
+            (_, true) => query.order(devices::id.desc()),
+            (_, false) => query.order(devices::id.asc()),
+        };
+        if let Some(limit) = pagination.limit {
+            query = query.limit(limit);
+        }
+        let devices : Vec<Device> = query.offset(pagination.offset).load(connection)?;
+        return Ok((devices, total));
+    }
+
     pub fn monitored(connection: &PgConnection) -> Vec<Device> {
         // TODO: default setting for polling enabled? NULL might mean false in that case..
         match devices::table
@@ -191,7 +515,8 @@ impl Device {
             Ok(result) => {
                 return result;
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::monitored", &error);
                 return Vec::new();
             }
         }
@@ -206,7 +531,8 @@ impl Device {
             Ok(interface) => {
                 return Some(interface);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::interface_by_name", &error);
                 return None;
             }
         }
@@ -221,7 +547,8 @@ impl Device {
             Ok(interface) => {
                 return Some(interface);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::interface_by_index", &error);
                 return None;
             }
         }
@@ -243,7 +570,8 @@ impl Device {
             Ok(device) => {
                 return Some(device);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::find_by_hostname_and_domain_name", &error);
                 return None;
             }
         }
@@ -262,7 +590,8 @@ impl Device {
             Ok(device) => {
                 return Some(device);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::find_by_fqdn", &error);
                 return None;
             }
         }
@@ -273,21 +602,30 @@ impl Device {
     }
 
     pub fn delete(self: &Device, connection: &PgConnection) -> Result<usize, diesel::result::Error> {
-        if let Ok(weathermap_device_info) = WeathermapDeviceInfo::belonging_to(self).load(connection) {
-            let wmdis : Vec<WeathermapDeviceInfo> = weathermap_device_info;
-            for wmdi in wmdis.iter() {
-                if let Err(_) = wmdi.delete(connection) {
-                    // TODO: log
+        let fqdn = format!("{}.{}", self.name, self.dns_domain);
+        match WeathermapDeviceInfo::belonging_to(self).load(connection) {
+            Ok(weathermap_device_info) => {
+                let wmdis : Vec<WeathermapDeviceInfo> = weathermap_device_info;
+                for wmdi in wmdis.iter() {
+                    if let Err(error) = wmdi.delete(connection) {
+                        log_error!("failed to delete weathermap info of device", "fqdn" => fqdn, "error" => error);
+                    }
                 }
-            }
-            for interface in self.interfaces(connection).iter() {
-                if let Err(d) = interface.delete(connection) {
-                    // TODO: log
-                    println!("{}", d);
+                for interface in self.interfaces(connection).iter() {
+                    if let Err(error) = interface.delete(connection) {
+                        log_error!("failed to delete interface of device", "fqdn" => fqdn, "interface" => interface.name, "error" => error);
+                    }
                 }
+            },
+            Err(error) => {
+                log_query_error("Device::delete", &error);
             }
         }
 
+        if let Err(error) = diesel::delete(device_credentials::table.filter(device_credentials::device_id.eq(self.id))).execute(connection) {
+            log_error!("failed to delete credentials of device", "fqdn" => fqdn, "error" => error);
+        }
+
         return diesel::delete(devices::table.find(self.id)).execute(connection);
     }
 
@@ -296,12 +634,26 @@ impl Device {
             Ok(result) => {
                 return result;
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::interfaces", &error);
                 return Vec::new();
             }
         }
     }
 
+    // One query for the interfaces of all given devices, grouped in the same order as devices
+    pub fn interfaces_grouped(devices: &Vec<Device>, connection: &PgConnection) -> Vec<Vec<Interface>> {
+        match Interface::belonging_to(devices).load::<Interface>(connection) {
+            Ok(result) => {
+                return result.grouped_by(devices);
+            },
+            Err(error) => {
+                log_query_error("Device::interfaces_grouped", &error);
+                return devices.iter().map(|_| Vec::new()).collect();
+            }
+        }
+    }
+
     pub fn weathermap_info(self: &Device, connection: &PgConnection) -> Option<WeathermapDeviceInfo> {
         match weathermap_device_infos::table
             .filter(weathermap_device_infos::device_id.eq(self.id))
@@ -310,11 +662,150 @@ impl Device {
             Ok(weathermap_device_info) => {
                 return Some(weathermap_device_info);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Device::weathermap_info", &error);
+                return None;
+            }
+        }
+    }
+}
+
+impl DeviceCredential {
+    pub fn by_device(device_id: i32, connection: &PgConnection) -> Option<DeviceCredential> {
+        match device_credentials::table
+            .filter(device_credentials::device_id.eq(device_id))
+            .first::<DeviceCredential>(connection)
+        {
+            Ok(device_credential) => {
+                return Some(device_credential);
+            },
+            Err(error) => {
+                log_query_error("DeviceCredential::by_device", &error);
+                return None;
+            }
+        }
+    }
+
+    pub fn all(connection: &PgConnection) -> Vec<DeviceCredential> {
+        match device_credentials::table.load(connection) {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("DeviceCredential::all", &error);
+                return Vec::new();
+            }
+        }
+    }
+
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<DeviceCredential>, diesel::result::Error> {
+        return device_credentials::table.order(device_credentials::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_device_credentials: &Vec<DeviceCredential>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_device_credentials.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(device_credentials::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+
+    // The community must already be encrypted by the caller
+    pub fn set_snmp_community(device_id: i32, snmp_community: Option<String>, connection: &PgConnection) -> Result<DeviceCredential, diesel::result::Error> {
+        match DeviceCredential::by_device(device_id, connection) {
+            Some(mut device_credential) => {
+                device_credential.snmp_community = snmp_community;
+                device_credential.update(connection)?;
+                return Ok(device_credential);
+            },
+            None => {
+                let new_device_credential = NewDeviceCredential {
+                    device_id: device_id,
+                    snmp_community: snmp_community,
+                };
+                return diesel::insert_into(device_credentials::table)
+                    .values(&new_device_credential)
+                    .get_result(connection);
+            }
+        }
+    }
+
+    pub fn update(self: &DeviceCredential, connection: &PgConnection) -> Result<usize, diesel::result::Error> {
+        return diesel::update(device_credentials::table.find(self.id)).set(self).execute(connection);
+    }
+}
+
+impl CredentialProfile {
+    pub fn create(new_credential_profile: &NewCredentialProfile, connection: &PgConnection) -> Result<CredentialProfile, diesel::result::Error> {
+        let result = diesel::insert_into(credential_profiles::table)
+            .values(new_credential_profile)
+            .get_result(connection);
+        return result;
+    }
+
+    pub fn update(self: &CredentialProfile, connection: &PgConnection) -> Result<usize, diesel::result::Error> {
+        return diesel::update(credential_profiles::table.find(self.id)).set(self).execute(connection);
+    }
+
+    pub fn delete(self: &CredentialProfile, connection: &PgConnection) -> Result<usize, diesel::result::Error> {
+        return diesel::delete(credential_profiles::table.find(self.id)).execute(connection);
+    }
+
+    pub fn by_name(name: &String, connection: &PgConnection) -> Option<CredentialProfile> {
+        match credential_profiles::table
+            .filter(credential_profiles::name.eq(name))
+            .first::<CredentialProfile>(connection)
+        {
+            Ok(credential_profile) => {
+                return Some(credential_profile);
+            },
+            Err(error) => {
+                log_query_error("CredentialProfile::by_name", &error);
                 return None;
             }
         }
     }
+
+    pub fn all(connection: &PgConnection) -> Vec<CredentialProfile> {
+        match credential_profiles::table
+            .order(credential_profiles::name.asc())
+            .load(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("CredentialProfile::all", &error);
+                return Vec::new();
+            }
+        }
+    }
+
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<CredentialProfile>, diesel::result::Error> {
+        return credential_profiles::table.order(credential_profiles::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_credential_profiles: &Vec<CredentialProfile>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_credential_profiles.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(credential_profiles::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+
+    pub fn device_count(self: &CredentialProfile, connection: &PgConnection) -> i64 {
+        match devices::table
+            .filter(devices::credential_profile_id.eq(self.id))
+            .count()
+            .get_result(connection)
+        {
+            Ok(count) => {
+                return count;
+            },
+            Err(error) => {
+                log_query_error("CredentialProfile::device_count", &error);
+                return 0;
+            }
+        }
+    }
 }
 
 impl Interface {
@@ -326,7 +817,8 @@ impl Interface {
             Ok(interface) => {
                 return Some(interface);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Interface::by_id", &error);
                 return None;
             }
         }
@@ -337,12 +829,74 @@ impl Interface {
             Ok(result) => {
                 return result;
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("Interface::all", &error);
                 return Vec::new();
             }
         }
     }
 
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<Interface>, diesel::result::Error> {
+        return interfaces::table.order(interfaces::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_interfaces: &Vec<Interface>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_interfaces.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(interfaces::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+
+    fn filtered<'a>(filter: &'a InterfaceFilter) -> interfaces::BoxedQuery<'a, Pg> {
+        let mut query = interfaces::table.into_boxed();
+        if let Some(device_id) = filter.device_id {
+            query = query.filter(interfaces::device_id.eq(device_id));
+        }
+        if let Some(ref name_prefix) = filter.name_prefix {
+            query = query.filter(interfaces::name.like(format!("{}%", like_escape(name_prefix))));
+        }
+        if let Some(ref interface_type) = filter.interface_type {
+            query = query.filter(interfaces::interface_type.eq(interface_type));
+        }
+        match filter.has_neighbor {
+            Some(true) => { query = query.filter(interfaces::connected_interface.is_not_null().or(interfaces::virtual_connection.is_not_null())); },
+            Some(false) => { query = query.filter(interfaces::connected_interface.is_null().and(interfaces::virtual_connection.is_null())); },
+            None => {},
+        }
+        if let Some(ref text) = filter.text {
+            let pattern = format!("%{}%", like_escape(text));
+            query = query.filter(
+                interfaces::name.ilike(pattern.clone())
+                .or(interfaces::display_name.ilike(pattern.clone()))
+                .or(interfaces::alias.ilike(pattern.clone()))
+                .or(interfaces::description.ilike(pattern))
+            );
+        }
+        return query;
+    }
+
+    // Returns the requested page and the number of interfaces matching the filter
+    pub fn search(filter: &InterfaceFilter, pagination: &Pagination, connection: &PgConnection) -> Result<(Vec<Interface>, i64), diesel::result::Error> {
+        let total : i64 = Interface::filtered(filter).count().get_result(connection)?;
+        let mut query = Interface::filtered(filter);
+        // id breaks ties so that pages do not overlap
+        query = match (pagination.sort.as_str(), pagination.descending) {
+            ("name", false) => query.order((interfaces::name.asc(), interfaces::id.asc())),
+            ("name", true) => query.order((interfaces::name.desc(), interfaces::id.desc())),
+            ("index", false) => query.order((interfaces::index.asc(), interfaces::id.asc())),
+            ("index", true) => query.order((interfaces::index.desc(), interfaces::id.desc())),
+            ("device", false) => query.order((interfaces::device_id.asc(), interfaces::index.asc(), interfaces::id.asc())),
+            ("device", true) => query.order((interfaces::device_id.desc(), interfaces::index.desc(), interfaces::id.desc())),
+            (_, true) => query.order(interfaces::id.desc()),
+            (_, false) => query.order(interfaces::id.asc()),
+        };
+        if let Some(limit) = pagination.limit {
+            query = query.limit(limit);
+        }
+        let interfaces : Vec<Interface> = query.offset(pagination.offset).load(connection)?;
+        return Ok((interfaces, total));
+    }
+
     pub fn create(new_interface: &NewInterface, connection: &PgConnection) -> Result<Interface, diesel::result::Error> {
         let result = diesel::insert_into(interfaces::table)
             .values(new_interface)
@@ -371,14 +925,27 @@ impl Interface {
             .load::<Interface>(connection)
         {
             Ok(mut peer_interface_vec) => {
+                // Only the reference pointing at this interface is cleared, the other one may still be valid
                 for peer_interface in peer_interface_vec.iter_mut() {
-                    peer_interface.connected_interface = None;
-                    if let Err(_) = peer_interface.update(connection) {
-                        // TODO: log
+                    if peer_interface.connected_interface == Some(self.id) { peer_interface.connected_interface = None; }
+                    if peer_interface.virtual_connection == Some(self.id) { peer_interface.virtual_connection = None; }
+                    if let Err(error) = peer_interface.update(connection) {
+                        log_error!("failed to clear peer connection of deleted interface", "interface_id" => self.id, "peer_interface_id" => peer_interface.id, "error" => error);
+                        return Err(error);
                     }
                 }
             },
-            Err(_) => {}
+            Err(error) => {
+                log_query_error("Interface::delete", &error);
+                return Err(error);
+            }
+        }
+        for mut member_interface in self.members(connection).into_iter() {
+            member_interface.aggregate_interface = None;
+            if let Err(error) = member_interface.update(connection) {
+                log_error!("failed to clear aggregate of member interface", "interface_id" => self.id, "member_interface_id" => member_interface.id, "error" => error);
+                return Err(error);
+            }
         }
         return diesel::delete(interfaces::table.find(self.id)).execute(connection);
     }
@@ -409,6 +976,21 @@ impl Interface {
         }
     }
 
+    pub fn members(self: &Interface, connection: &PgConnection) -> Vec<Interface> {
+        match interfaces::table
+            .filter(interfaces::aggregate_interface.eq(self.id))
+            .load::<Interface>(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("Interface::members", &error);
+                return Vec::new();
+            }
+        }
+    }
+
     pub fn device(self: &Interface, connection: &PgConnection) -> Device {
         return Device::by_id(self.device_id, connection).unwrap();
     }
@@ -426,6 +1008,32 @@ impl WeathermapDeviceInfo {
         return diesel::update(weathermap_device_infos::table.find(self.id)).set(self).execute(connection);
     }
 
+    pub fn all(connection: &PgConnection) -> Vec<WeathermapDeviceInfo> {
+        match weathermap_device_infos::table
+            .order(weathermap_device_infos::id.asc())
+            .load(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("WeathermapDeviceInfo::all", &error);
+                return Vec::new();
+            }
+        }
+    }
+
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<WeathermapDeviceInfo>, diesel::result::Error> {
+        return weathermap_device_infos::table.order(weathermap_device_infos::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_weathermap_device_infos: &Vec<WeathermapDeviceInfo>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_weathermap_device_infos.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(weathermap_device_infos::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+
     pub fn lookup_by_device(connection: &PgConnection, device: &Device) -> Option<WeathermapDeviceInfo> {
         match weathermap_device_infos::table
             .filter(weathermap_device_infos::device_id.eq(device.id))
@@ -434,7 +1042,8 @@ impl WeathermapDeviceInfo {
             Ok(weathermap_device_info) => {
                 return Some(weathermap_device_info);
             },
-            Err(_) => {
+            Err(error) => {
+                log_query_error("WeathermapDeviceInfo::lookup_by_device", &error);
                 return None;
             }
         }
@@ -444,7 +1053,7 @@ impl WeathermapDeviceInfo {
         return diesel::delete(weathermap_device_infos::table.find(self.id)).execute(connection);
     }
 
-    pub fn update_by_fqdn_or_create(connection: &PgConnection, fqdn: &String, updated_info: UpdatedWeathermapDeviceInfo) -> Result<WeathermapDeviceInfo, String> {
+    pub fn update_by_fqdn_or_create(connection: &PgConnection, fqdn: &String, updated_info: UpdatedWeathermapDeviceInfo) -> Result<WeathermapDeviceInfo, diesel::result::Error> {
         if let Some(device) = Device::find_by_fqdn(connection, fqdn) {
             let mut wmap_info;
             if let Some(weathermap_info) = WeathermapDeviceInfo::lookup_by_device(connection, &device) {
@@ -454,10 +1063,14 @@ impl WeathermapDeviceInfo {
                 wmap_info.expanded_by_default = updated_info.expanded_by_default;
                 wmap_info.super_node = updated_info.super_node;
 
-                if let Ok(_) = wmap_info.update(connection) {
-                    return Ok(wmap_info);
-                } else {
-                    return Err("failed to update WeathermapDeviceInfo".to_string());
+                match wmap_info.update(connection) {
+                    Ok(_) => {
+                        return Ok(wmap_info);
+                    },
+                    Err(error) => {
+                        log_error!("failed to update weathermap info", "fqdn" => fqdn, "error" => error);
+                        return Err(error);
+                    }
                 }
             } else {
                 let template = NewWeathermapDeviceInfo {
@@ -467,14 +1080,18 @@ impl WeathermapDeviceInfo {
                     super_node: updated_info.super_node,
                     device_id: device.id,
                 };
-                if let Ok(wmap_created_object) = WeathermapDeviceInfo::create(&template, connection) {
-                    return Ok(wmap_created_object);
-                } else {
-                    return Err("couldn't create WeathermapDeviceInfo".to_string());
+                match WeathermapDeviceInfo::create(&template, connection) {
+                    Ok(wmap_created_object) => {
+                        return Ok(wmap_created_object);
+                    },
+                    Err(error) => {
+                        log_error!("failed to create weathermap info", "fqdn" => fqdn, "error" => error);
+                        return Err(error);
+                    }
                 }
             }
         } else {
-            return Err("device not found".to_string());
+            return Err(diesel::result::Error::NotFound);
         }
     }
 
@@ -482,3 +1099,199 @@ impl WeathermapDeviceInfo {
         return Device::by_id(self.device_id, connection).unwrap();
     }
 }
+
+impl TopologySnapshot {
+    pub fn create(new_topology_snapshot: &NewTopologySnapshot, connection: &PgConnection) -> Result<TopologySnapshot, diesel::result::Error> {
+        let result = diesel::insert_into(topology_snapshots::table)
+            .values(new_topology_snapshot)
+            .get_result(connection);
+        return result;
+    }
+
+    pub fn by_id(id: i32, connection: &PgConnection) -> Option<TopologySnapshot> {
+        match topology_snapshots::table
+            .filter(topology_snapshots::id.eq(id))
+            .first::<TopologySnapshot>(connection)
+        {
+            Ok(topology_snapshot) => {
+                return Some(topology_snapshot);
+            },
+            Err(error) => {
+                log_query_error("TopologySnapshot::by_id", &error);
+                return None;
+            }
+        }
+    }
+
+    pub fn latest(connection: &PgConnection) -> Option<TopologySnapshot> {
+        match topology_snapshots::table
+            .order(topology_snapshots::id.desc())
+            .first::<TopologySnapshot>(connection)
+        {
+            Ok(topology_snapshot) => {
+                return Some(topology_snapshot);
+            },
+            Err(error) => {
+                log_query_error("TopologySnapshot::latest", &error);
+                return None;
+            }
+        }
+    }
+
+    pub fn summaries(connection: &PgConnection) -> Vec<TopologySnapshotSummary> {
+        match topology_snapshots::table
+            .select((topology_snapshots::id, topology_snapshots::created_at, topology_snapshots::reason))
+            .order(topology_snapshots::id.asc())
+            .load::<TopologySnapshotSummary>(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("TopologySnapshot::summaries", &error);
+                return Vec::new();
+            }
+        }
+    }
+
+    pub fn load_all(connection: &PgConnection) -> Result<Vec<TopologySnapshot>, diesel::result::Error> {
+        return topology_snapshots::table.order(topology_snapshots::id.asc()).load(connection);
+    }
+
+    pub fn restore(restored_topology_snapshots: &Vec<TopologySnapshot>, connection: &PgConnection) -> Result<(), diesel::result::Error> {
+        for chunk in restored_topology_snapshots.chunks(RESTORE_CHUNK_SIZE) {
+            diesel::insert_into(topology_snapshots::table).values(chunk).execute(connection)?;
+        }
+        return Ok(());
+    }
+}
+
+impl ApiToken {
+    pub fn create(new_api_token: &NewApiToken, connection: &PgConnection) -> Result<ApiToken, diesel::result::Error> {
+        let result = diesel::insert_into(api_tokens::table)
+            .values(new_api_token)
+            .get_result(connection);
+        return result;
+    }
+
+    pub fn update(self: &ApiToken, connection: &PgConnection) -> Result<usize, diesel::result::Error> {
+        return diesel::update(api_tokens::table.find(self.id)).set(self).execute(connection);
+    }
+
+    pub fn by_id(id: i32, connection: &PgConnection) -> Option<ApiToken> {
+        match api_tokens::table
+            .filter(api_tokens::id.eq(id))
+            .first::<ApiToken>(connection)
+        {
+            Ok(api_token) => {
+                return Some(api_token);
+            },
+            Err(error) => {
+                log_query_error("ApiToken::by_id", &error);
+                return None;
+            }
+        }
+    }
+
+    pub fn by_hash(token_hash: &String, connection: &PgConnection) -> Result<Option<ApiToken>, diesel::result::Error> {
+        match api_tokens::table
+            .filter(api_tokens::token_hash.eq(token_hash))
+            .first::<ApiToken>(connection)
+        {
+            Ok(api_token) => {
+                return Ok(Some(api_token));
+            },
+            Err(diesel::result::Error::NotFound) => {
+                return Ok(None);
+            },
+            Err(error) => {
+                return Err(error);
+            }
+        }
+    }
+
+    pub fn all(connection: &PgConnection) -> Vec<ApiToken> {
+        match api_tokens::table
+            .order(api_tokens::id.asc())
+            .load(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("ApiToken::all", &error);
+                return Vec::new();
+            }
+        }
+    }
+
+    pub fn scope_list(self: &ApiToken) -> Vec<String> {
+        return self.scopes.split(',').map(|scope| scope.trim().to_string()).filter(|scope| !scope.is_empty()).collect();
+    }
+
+    pub fn usages(self: &ApiToken, connection: &PgConnection) -> Vec<ApiTokenUsage> {
+        match api_token_usages::table
+            .filter(api_token_usages::token_id.eq(self.id))
+            .order(api_token_usages::id.desc())
+            .load(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("ApiToken::usages", &error);
+                return Vec::new();
+            }
+        }
+    }
+}
+
+impl ApiTokenUsage {
+    pub fn create(new_api_token_usage: &NewApiTokenUsage, connection: &PgConnection) -> Result<ApiTokenUsage, diesel::result::Error> {
+        let result = diesel::insert_into(api_token_usages::table)
+            .values(new_api_token_usage)
+            .get_result(connection);
+        return result;
+    }
+}
+
+impl AuditEntry {
+    pub fn create(new_audit_entry: &NewAuditEntry, connection: &PgConnection) -> Result<AuditEntry, diesel::result::Error> {
+        let result = diesel::insert_into(audit_entries::table)
+            .values(new_audit_entry)
+            .get_result(connection);
+        return result;
+    }
+
+    pub fn search(entity_type: &Option<String>, entity_key: &Option<String>, token_name: &Option<String>, since: Option<f64>, until: Option<f64>, limit: i64, connection: &PgConnection) -> Vec<AuditEntry> {
+        let mut query = audit_entries::table.into_boxed();
+        if let Some(ref entity_type) = *entity_type {
+            query = query.filter(audit_entries::entity_type.eq(entity_type));
+        }
+        if let Some(ref entity_key) = *entity_key {
+            query = query.filter(audit_entries::entity_key.eq(entity_key));
+        }
+        if let Some(ref token_name) = *token_name {
+            query = query.filter(audit_entries::token_name.eq(token_name));
+        }
+        if let Some(since) = since {
+            query = query.filter(audit_entries::created_at.ge(since));
+        }
+        if let Some(until) = until {
+            query = query.filter(audit_entries::created_at.lt(until));
+        }
+        match query
+            .order(audit_entries::id.desc())
+            .limit(limit)
+            .load(connection)
+        {
+            Ok(result) => {
+                return result;
+            },
+            Err(error) => {
+                log_query_error("AuditEntry::search", &error);
+                return Vec::new();
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use models;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Serialize, Deserialize)]
@@ -38,6 +39,19 @@ pub struct InterfaceSpeedEvent {
     new_state: i32,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LagDegradedEvent {
+    fqdn: String,
+    name: String,
+    member_name: String,
+
+    members_state: HashMap<String,String>,
+
+    old_state: bool,
+    new_state: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DevicePollingChangedEvent {
@@ -74,6 +88,29 @@ pub struct DeviceDeletedEvent {
     fqdn: String,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceRenamedEvent {
+    old_fqdn: String,
+    new_fqdn: String,
+    base_mac: String,
+}
+
+// Settings carried over from the old name, the interface keeps its links as well
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterfaceRenamedEvent {
+    fqdn: String,
+    old_name: String,
+    new_name: String,
+    index: i32,
+    matched_by: String,
+
+    display_name: Option<String>,
+    polling_enabled: Option<bool>,
+    speed_override: Option<i32>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Event {
@@ -88,6 +125,9 @@ pub struct Event {
     #[serde(skip_serializing_if="Option::is_none")]
     interface_speed: Option<InterfaceSpeedEvent>,
 
+    #[serde(skip_serializing_if="Option::is_none")]
+    lag_degraded: Option<LagDegradedEvent>,
+
     #[serde(skip_serializing_if="Option::is_none")]
     device_polling_changed: Option<DevicePollingChangedEvent>,
 
@@ -102,6 +142,12 @@ pub struct Event {
 
     #[serde(skip_serializing_if="Option::is_none")]
     device_deleted_event: Option<DeviceDeletedEvent>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    device_renamed: Option<DeviceRenamedEvent>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    interface_renamed: Option<InterfaceRenamedEvent>,
 }
 
 impl Event {
@@ -111,11 +157,14 @@ impl Event {
             ping_change: None,
             interface_up_down: None,
             interface_speed: None,
+            lag_degraded: None,
             device_polling_changed: None,
             device_os_info_changed: None,
             device_base_mac_changed: None,
             device_created_event: None,
             device_deleted_event: None,
+            device_renamed: None,
+            interface_renamed: None,
         };
 
         return event;
@@ -167,6 +216,31 @@ impl Event {
         return event;
     }
 
+    pub fn device_renamed_event(old_fqdn: &String, new_fqdn: &String, base_mac: &String) -> Event {
+        let mut event = Event::new_empty("deviceRenamed");
+        event.device_renamed = Some(DeviceRenamedEvent {
+            old_fqdn: old_fqdn.clone(),
+            new_fqdn: new_fqdn.clone(),
+            base_mac: base_mac.clone(),
+        });
+        return event;
+    }
+
+    pub fn interface_renamed_event(fqdn: &String, old_name: &String, interface: &models::dbo::Interface, matched_by: &str) -> Event {
+        let mut event = Event::new_empty("interfaceRenamed");
+        event.interface_renamed = Some(InterfaceRenamedEvent {
+            fqdn: fqdn.clone(),
+            old_name: old_name.clone(),
+            new_name: interface.name.clone(),
+            index: interface.index,
+            matched_by: matched_by.to_string(),
+            display_name: interface.display_name.clone(),
+            polling_enabled: interface.polling_enabled,
+            speed_override: interface.speed_override,
+        });
+        return event;
+    }
+
     pub fn ping_change_event(fqdn: &String, neighbors: HashSet<String>, old_state: bool, new_state: bool) -> Event {
         let mut event = Event::new_empty("pingChange");
 
@@ -222,4 +296,21 @@ impl Event {
 
         return event;
     }
+
+    pub fn lag_degraded_event(fqdn: &String, name: &String, member_name: &String, members_state: &HashMap<String, String>, old_state: bool, new_state: bool) -> Event {
+        let mut event = Event::new_empty("lagDegraded");
+
+        let lde = LagDegradedEvent {
+            fqdn: fqdn.clone(),
+            name: name.clone(),
+            member_name: member_name.clone(),
+            members_state: members_state.clone(),
+            old_state: old_state,
+            new_state: new_state,
+        };
+
+        event.lag_degraded = Some(lde);
+
+        return event;
+    }
 }
\ No newline at end of file
@@ -2,12 +2,16 @@ use utilities::tools;
 
 pub struct RuntimeInfo {
     pub startup_time: f64,
+    pub imds_worker_first_run_done: bool,
+    pub imds_worker_last_iteration: Option<f64>,
 }
 
 impl RuntimeInfo {
     pub fn new() -> RuntimeInfo {
         return RuntimeInfo {
             startup_time: tools::get_time(),
+            imds_worker_first_run_done: false,
+            imds_worker_last_iteration: None,
         };
     }
     pub fn state_id(self: &RuntimeInfo) -> i64 {
@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use models;
+use serde::{Deserialize, Deserializer};
+use serde_json;
+use std::collections::{HashMap, BTreeMap};
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -6,11 +9,121 @@ pub struct DiscoveredDevice {
     pub name: String,
     pub dns_domain: String,
     pub snmp_community: Option<String>,
+    // Profile name, the current profile is kept when this is not reported
+    pub credential_profile: Option<String>,
     pub base_mac: Option<String>,
     pub os_info: Option<String>,
     pub interfaces : HashMap<String, DiscoveredInterface>,
 }
 
+// Request body of PUT /device, the community is split off into the credentials store
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInput {
+    pub name: String,
+    pub dns_domain: String,
+    pub snmp_community: Option<String>,
+    pub credential_profile: Option<String>,
+    pub base_mac: Option<String>,
+    pub polling_enabled: Option<bool>,
+    pub os_info: Option<String>,
+}
+
+impl DeviceInput {
+    pub fn new_device(self: &DeviceInput, credential_profile_id: Option<i32>) -> models::dbo::NewDevice {
+        return models::dbo::NewDevice {
+            name: self.name.clone(),
+            dns_domain: self.dns_domain.clone(),
+            base_mac: self.base_mac.clone(),
+            polling_enabled: self.polling_enabled.clone(),
+            os_info: self.os_info.clone(),
+            credential_profile_id: credential_profile_id,
+        };
+    }
+
+    // PUT replaces every field, an omitted one is cleared
+    pub fn patch(self: &DeviceInput) -> DevicePatch {
+        return DevicePatch {
+            snmp_community: Some(self.snmp_community.clone()),
+            credential_profile: Some(self.credential_profile.clone()),
+            base_mac: Some(self.base_mac.clone()),
+            polling_enabled: Some(self.polling_enabled.clone()),
+            os_info: Some(self.os_info.clone()),
+        };
+    }
+}
+
+// Tells a field set to null (Some(None)) apart from an omitted one (None)
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error> where T: Deserialize<'de>, D: Deserializer<'de> {
+    return Deserialize::deserialize(deserializer).map(Some);
+}
+
+// Request body of PATCH /device/<id>, only the present fields are changed
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DevicePatch {
+    #[serde(default, deserialize_with = "double_option")]
+    pub snmp_community: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub credential_profile: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub base_mac: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub polling_enabled: Option<Option<bool>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub os_info: Option<Option<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCredentialInfo {
+    pub fqdn : String,
+    pub snmp_community : Option<String>,
+    pub credential_profile : Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialProfileRequest {
+    pub name : String,
+    pub version : String,
+    pub community : Option<String>,
+    pub username : Option<String>,
+    pub auth_protocol : Option<String>,
+    pub auth_password : Option<String>,
+    pub priv_protocol : Option<String>,
+    pub priv_password : Option<String>,
+}
+
+// Admin view of a profile, secrets are only reported as present or not
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialProfileInfo {
+    pub id : i32,
+    pub name : String,
+    pub version : String,
+    pub username : Option<String>,
+    pub auth_protocol : Option<String>,
+    pub priv_protocol : Option<String>,
+    pub has_community : bool,
+    pub has_auth_password : bool,
+    pub has_priv_password : bool,
+    pub device_count : i64,
+}
+
+// Decrypted profile as served to pollers
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnmpCredentials {
+    pub version : String,
+    pub community : Option<String>,
+    pub username : Option<String>,
+    pub auth_protocol : Option<String>,
+    pub auth_password : Option<String>,
+    pub priv_protocol : Option<String>,
+    pub priv_password : Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DiscoveredInterface {
@@ -20,6 +133,7 @@ pub struct DiscoveredInterface {
     pub name: String,
     pub alias: Option<String>,
     pub description: Option<String>,
+    pub aggregate_index: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -43,6 +157,7 @@ pub struct LinkInfo {
 pub struct DeviceMonitorInfo {
     pub fqdn : String,
     pub up : Option<bool>,
+    pub credential_profile : Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -50,6 +165,8 @@ pub struct DeviceMonitorInfo {
 pub struct DeviceMonitorResponse {
     pub state_id : i64,
     pub devices : Vec<DeviceMonitorInfo>,
+    // Keyed by profile name, only profiles referenced by the listed devices
+    pub credential_profiles : HashMap<String, SnmpCredentials>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -59,6 +176,15 @@ pub struct DeviceMonitorReport {
     pub up : bool,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonitorReportResult {
+    pub fqdn : String,
+    pub accepted : bool,
+    pub unknown_device : bool,
+    pub unknown_if_indexes : Vec<i32>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InterfaceMonitorInterfaceReport {
@@ -163,3 +289,297 @@ pub struct ClientLocationInfo {
     pub yiaddr: String,
     pub option82: HashMap<String, String>,
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyPathClient {
+    pub ip_address: String,
+    pub fqdn: String,
+    pub port_info: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyPathInterface {
+    pub fqdn: String,
+    pub interface: String,
+    pub if_index: i32,
+    pub up: Option<bool>,
+    pub speed: Option<i32>,
+    pub rx_bps: Option<f64>,
+    pub tx_bps: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyPathHop {
+    pub from: TopologyPathInterface,
+    pub to: TopologyPathInterface,
+    pub utilization: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyPath {
+    pub client: Option<TopologyPathClient>,
+    pub devices: Vec<String>,
+    pub hops: Vec<TopologyPathHop>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyLinkInfo {
+    pub fqdn: String,
+    pub interface: String,
+    pub peer_fqdn: String,
+    pub peer_interface: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySpofReport {
+    pub articulation_points: Vec<String>,
+    pub bridges: Vec<TopologyLinkInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologyImpactReport {
+    pub roots: Vec<String>,
+    pub removed_device: Option<String>,
+    pub removed_link: Option<TopologyLinkInfo>,
+    pub unreachable: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySingleUplink {
+    pub fqdn: String,
+    pub uplink: TopologyLinkInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySnapshotInterface {
+    pub fqdn: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySnapshotData {
+    pub devices: Vec<String>,
+    pub interfaces: Vec<TopologySnapshotInterface>,
+    pub links: Vec<TopologyLinkInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySnapshotInfo {
+    pub id: i32,
+    pub created_at: f64,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySnapshotDiff {
+    pub from: i32,
+    pub to: Option<i32>,
+    pub added_devices: Vec<String>,
+    pub removed_devices: Vec<String>,
+    pub added_interfaces: Vec<TopologySnapshotInterface>,
+    pub removed_interfaces: Vec<TopologySnapshotInterface>,
+    pub added_links: Vec<TopologyLinkInfo>,
+    pub removed_links: Vec<TopologyLinkInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheck {
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub status: String,
+    pub checks: HashMap<String, HealthCheck>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiErrorBody {
+    pub status: u16,
+    pub error: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenInfo {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: f64,
+    pub revoked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiToken {
+    pub info: ApiTokenInfo,
+    // Only returned here, the database keeps a hash
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntryInfo {
+    pub id: i32,
+    pub created_at: f64,
+    pub token_id: Option<i32>,
+    pub token_name: String,
+    pub entity_type: String,
+    pub entity_key: String,
+    pub action: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryPosition {
+    pub x: f64,
+    pub y: f64,
+    #[serde(default)]
+    pub super_node: bool,
+    #[serde(default)]
+    pub expanded_by_default: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryInterfaceRef {
+    pub device: String,
+    pub interface: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryInterface {
+    pub name: String,
+    pub index: i32,
+    pub interface_type: String,
+    pub display_name: Option<String>,
+    pub alias: Option<String>,
+    pub description: Option<String>,
+    pub polling_enabled: Option<bool>,
+    pub speed_override: Option<i32>,
+    pub virtual_connection: Option<InventoryInterfaceRef>,
+}
+
+// Credentials are never part of an inventory, only the profile name
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryDevice {
+    pub name: String,
+    pub dns_domain: String,
+    pub base_mac: Option<String>,
+    pub polling_enabled: Option<bool>,
+    pub os_info: Option<String>,
+    pub credential_profile: Option<String>,
+    pub position: Option<InventoryPosition>,
+    #[serde(default)]
+    pub interfaces: Vec<InventoryInterface>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Inventory {
+    pub version: u32,
+    pub devices: Vec<InventoryDevice>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryChange {
+    pub entity: String,
+    pub key: String,
+    pub action: String,
+    pub fields: BTreeMap<String, FieldChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryImportResult {
+    pub dry_run: bool,
+    pub devices: usize,
+    pub interfaces: usize,
+    pub changes: Vec<InventoryChange>,
+}
+
+// Rows are kept as stored, including ids and encrypted credentials
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupArchive {
+    pub version: u32,
+    pub schema_version: String,
+    pub created_at: f64,
+    pub credential_profiles: Vec<models::dbo::CredentialProfile>,
+    pub devices: Vec<models::dbo::Device>,
+    pub device_credentials: Vec<models::dbo::DeviceCredential>,
+    pub interfaces: Vec<models::dbo::Interface>,
+    pub weathermap_device_infos: Vec<models::dbo::WeathermapDeviceInfo>,
+    pub client_locations: Vec<models::dbo::ClientLocation>,
+    pub topology_snapshots: Vec<models::dbo::TopologySnapshot>,
+}
+
+// Credentials are left out, they never appear in a diff
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryDeviceDiff {
+    pub dry_run: bool,
+    pub device_fqdn: String,
+    pub device_created: bool,
+    // Previous fqdn when the device was recognized by its base MAC under a new name
+    pub renamed_from: Option<String>,
+    pub device_changes: BTreeMap<String, FieldChange>,
+    pub interfaces_added: Vec<String>,
+    pub interfaces_removed: Vec<String>,
+    // Old name to new name
+    pub interfaces_renamed: BTreeMap<String, String>,
+    pub interfaces_updated: BTreeMap<String, BTreeMap<String, FieldChange>>,
+}
+
+// Keyed by "<device fqdn> <interface name>", set links map to their new peer in the same form
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryLinksDiff {
+    pub dry_run: bool,
+    pub device_fqdn: String,
+    pub links_set: BTreeMap<String, String>,
+    pub links_cleared: Vec<String>,
+}
+
+// Query options of the routes that can compute their changes without storing them
+#[derive(FromForm)]
+pub struct DryRunOptions {
+    #[form(field = "dryRun")]
+    pub dry_run: Option<bool>,
+}
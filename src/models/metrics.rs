@@ -8,6 +8,7 @@ pub struct InterfaceMetrics {
     pub neighbors: bool,
     pub interface_type: String,
     pub speed_override: Option<i32>,
+    pub aggregate_index: Option<i32>,
 
     pub in_octets: Option<u64>,
     pub out_octets: Option<u64>,
@@ -22,6 +23,11 @@ pub struct InterfaceMetrics {
     pub out_discards: Option<u64>,
     pub up: Option<bool>,
     pub speed: Option<i32>,
+
+    pub in_octets_timestamp: Option<f64>,
+    pub out_octets_timestamp: Option<f64>,
+    pub in_bps: Option<f64>,
+    pub out_bps: Option<f64>,
 }
 
 pub struct DeviceMetrics {
@@ -33,6 +39,8 @@ pub struct DeviceMetrics {
 
     pub up: Option<bool>,
 
+    pub last_report: Option<f64>,
+
     pub interfaces: HashMap<i32, InterfaceMetrics>,
 }
 
@@ -40,6 +48,12 @@ pub struct Metrics {
     pub devices: HashMap<String, DeviceMetrics>,
 }
 
+pub struct UnknownReportCounters {
+    pub last_seen: f64,
+    pub device_reports: u64,
+    pub interface_reports: u64,
+}
+
 pub struct DeviceMetricRefreshCacheMiss {
     pub miss_set: HashSet<String>,
 }
@@ -56,6 +70,7 @@ impl DeviceMetricRefreshCacheMiss {
 pub enum MetricValue {
     Int64(i64),
     Uint64(u64),
+    Float64(f64),
 }
 
 pub struct LabeledMetric {
@@ -86,6 +101,9 @@ impl LabeledMetric {
             },
             MetricValue::Uint64(value) => {
                 body = format!("{}{{{}}} {}", self.name, labeltext, value);
+            },
+            MetricValue::Float64(value) => {
+                body = format!("{}{{{}}} {}", self.name, labeltext, value);
             }
         }
         return format!("{}", body);
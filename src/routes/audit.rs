@@ -0,0 +1,24 @@
+extern crate rocket_contrib;
+use models;
+use db;
+use rocket::get;
+use rocket_contrib::json;
+use utilities;
+use utilities::auth::{Authorized, AdminScope};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+// Newest entries first, since and until are unix timestamps
+#[get("/?<entity_type>&<entity_key>&<token>&<since>&<until>&<limit>")]
+pub fn audit_list(_auth: Authorized<AdminScope>, connection: db::Connection, entity_type: Option<String>, entity_key: Option<String>, token: Option<String>, since: Option<f64>, until: Option<f64>, limit: Option<i64>) -> Result<json::Json<Vec<models::json::AuditEntryInfo>>, utilities::errors::ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit < 1 || limit > MAX_LIMIT {
+        return Err(utilities::errors::ApiError::BadRequest(format!("limit must be between 1 and {}", MAX_LIMIT)));
+    }
+    let mut audit_entry_infos : Vec<models::json::AuditEntryInfo> = Vec::new();
+    for audit_entry in models::dbo::AuditEntry::search(&entity_type, &entity_key, &token, since, until, limit, &connection).iter() {
+        audit_entry_infos.push(utilities::audit::entry_info(audit_entry));
+    }
+    return Ok(json::Json(audit_entry_infos));
+}
@@ -0,0 +1,43 @@
+extern crate rocket_contrib;
+use models;
+use rocket::{catch, Request};
+use rocket::http::Status;
+use rocket_contrib::json;
+use utilities;
+
+// Keeps failures raised by Rocket itself (guards, malformed JSON, unknown routes) in the same JSON shape as ApiError
+
+#[catch(400)]
+pub fn bad_request(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::BadRequest, format!("malformed request to {}", request.uri()));
+}
+
+#[catch(404)]
+pub fn not_found(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::NotFound, format!("{} not found", request.uri()));
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::UnprocessableEntity, format!("request body to {} could not be parsed", request.uri()));
+}
+
+#[catch(500)]
+pub fn internal_error(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::InternalServerError, format!("internal error while handling {}", request.uri()));
+}
+
+#[catch(503)]
+pub fn service_unavailable(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::ServiceUnavailable, format!("service unavailable while handling {}", request.uri()));
+}
+
+#[catch(401)]
+pub fn unauthorized(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::Unauthorized, format!("a valid API token is required for {}", request.uri()));
+}
+
+#[catch(403)]
+pub fn forbidden(request: &Request) -> json::Json<models::json::ApiErrorBody> {
+    return utilities::errors::error_body(Status::Forbidden, format!("the API token is not allowed to access {}", request.uri()));
+}
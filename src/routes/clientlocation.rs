@@ -3,21 +3,23 @@ use rocket::{get, put};
 use models;
 use db;
 use rocket_contrib::json;
+use utilities;
+use utilities::auth::{Authorized, DiscoveryScope};
 
 #[put("/", data = "<client_location_info_json>")]
-pub fn put_clientlocation(connection: db::Connection, client_location_info_json: json::Json<models::json::ClientLocationInfo>) {
+pub fn put_clientlocation(_auth: Authorized<DiscoveryScope>, connection: db::Connection, client_location_info_json: json::Json<models::json::ClientLocationInfo>) -> Result<(), utilities::errors::ApiError> {
     let client_location_info: models::json::ClientLocationInfo = client_location_info_json.into_inner();
     let option82_001: String;
     let option82_002: String;
     if let Some(opt82_001) = client_location_info.option82.get(&"001".to_string()) {
         option82_001 = opt82_001.clone();
     } else {
-        return;
+        return Err(utilities::errors::ApiError::BadRequest("option82 sub-option 001 (circuit id) missing".to_string()));
     }
     if let Some(opt82_002) = client_location_info.option82.get(&"002".to_string()) {
         option82_002 = opt82_002.clone();
     } else {
-        return;
+        return Err(utilities::errors::ApiError::BadRequest("option82 sub-option 002 (remote id) missing".to_string()));
     }
 
     let option82_001_hex: Vec<&str> = option82_001.split(":").collect();
@@ -28,13 +30,13 @@ pub fn put_clientlocation(connection: db::Connection, client_location_info_json:
         if let Ok(module_res) = i32::from_str_radix(option82_001_hex[4], 16) {
             module = module_res;
         } else {
-            return;
+            return Err(utilities::errors::ApiError::BadRequest(format!("malformed option82 circuit id {}", option82_001)));
         }
         let port: i32;
         if let Ok(port_res) = i32::from_str_radix(option82_001_hex[5], 16) {
             port = port_res;
         } else {
-            return;
+            return Err(utilities::errors::ApiError::BadRequest(format!("malformed option82 circuit id {}", option82_001)));
         }
         let port_info = format!("{}/{}", module, port);
         let switch_base_mac = format!(
@@ -46,14 +48,16 @@ pub fn put_clientlocation(connection: db::Connection, client_location_info_json:
         if let Some(some_device) = models::dbo::Device::by_base_mac(&switch_base_mac, &connection) {
             device = some_device;
         } else {
-            return;
+            log_debug!("no device for client location base mac", "route" => "PUT /clientlocation", "base_mac" => switch_base_mac, "ip_address" => client_location_info.yiaddr);
+            return Err(utilities::errors::ApiError::NotFound(format!("no device with base mac {}", switch_base_mac)));
         }
         if let Some(mut existing_client_info) = models::dbo::ClientLocation::by_ip(&client_location_info.yiaddr, &connection) {
             if existing_client_info.port_info != port_info || existing_client_info.device_id != device.id {
                 existing_client_info.port_info = port_info;
                 existing_client_info.device_id = device.id;
-                if let Err(_update_error) = existing_client_info.update(&connection) {
-                    // TODO: log
+                if let Err(update_error) = existing_client_info.update(&connection) {
+                    log_error!("failed to update client location", "route" => "PUT /clientlocation", "ip_address" => client_location_info.yiaddr, "error" => update_error);
+                    return Err(utilities::errors::ApiError::from(update_error));
                 }
             }
         } else {
@@ -62,10 +66,13 @@ pub fn put_clientlocation(connection: db::Connection, client_location_info_json:
                 ip_address: client_location_info.yiaddr.clone(),
                 port_info: port_info.clone()
             };
-            if let Ok(_new_client_location) = models::dbo::ClientLocation::create(&l, &connection) {
-            } else {
-                // TODO: log this
+            if let Err(create_error) = models::dbo::ClientLocation::create(&l, &connection) {
+                log_error!("failed to create client location", "route" => "PUT /clientlocation", "ip_address" => client_location_info.yiaddr, "error" => create_error);
+                return Err(utilities::errors::ApiError::from(create_error));
             }
         }
+    } else {
+        return Err(utilities::errors::ApiError::BadRequest(format!("malformed option82 circuit id {} or remote id {}", option82_001, option82_002)));
     }
+    return Ok(());
 }
@@ -0,0 +1,92 @@
+extern crate rocket_contrib;
+use models;
+use db;
+use rocket::{get, put, delete};
+use rocket::State;
+use rocket_contrib::json;
+use serde_json;
+use utilities;
+use utilities::auth::{Authorized, AdminScope};
+
+fn credential_profile_info(credential_profile: &models::dbo::CredentialProfile, connection: &db::Connection) -> models::json::CredentialProfileInfo {
+    return models::json::CredentialProfileInfo {
+        id: credential_profile.id,
+        name: credential_profile.name.clone(),
+        version: credential_profile.snmp_version.clone(),
+        username: credential_profile.username.clone(),
+        auth_protocol: credential_profile.auth_protocol.clone(),
+        priv_protocol: credential_profile.priv_protocol.clone(),
+        has_community: credential_profile.community.is_some(),
+        has_auth_password: credential_profile.auth_password.is_some(),
+        has_priv_password: credential_profile.priv_password.is_some(),
+        device_count: credential_profile.device_count(&connection),
+    };
+}
+
+#[get("/")]
+pub fn credential_profile_list(_auth: Authorized<AdminScope>, connection: db::Connection) -> json::Json<Vec<models::json::CredentialProfileInfo>> {
+    let mut credential_profile_infos : Vec<models::json::CredentialProfileInfo> = Vec::new();
+    for credential_profile in models::dbo::CredentialProfile::all(&connection).iter() {
+        credential_profile_infos.push(credential_profile_info(credential_profile, &connection));
+    }
+    return json::Json(credential_profile_infos);
+}
+
+// Replaces the whole profile, secrets have to be sent again as they are never returned
+#[put("/", data = "<profile_request>")]
+pub fn credential_profile_create_or_modify(auth: Authorized<AdminScope>, connection: db::Connection, profile_request: json::Json<models::json::CredentialProfileRequest>, credential_cipher: State<utilities::credentials::CredentialCipher>) -> Result<json::Json<models::json::CredentialProfileInfo>, utilities::errors::ApiError> {
+    let profile_request = profile_request.into_inner();
+    if let Err(error) = utilities::credentials::validate_profile(&profile_request) {
+        return Err(utilities::errors::ApiError::BadRequest(error));
+    }
+    let new_credential_profile = match utilities::credentials::new_credential_profile(&profile_request, &credential_cipher) {
+        Ok(new_credential_profile) => new_credential_profile,
+        Err(error) => { return Err(utilities::errors::ApiError::Internal(error)); }
+    };
+
+    let credential_profile : models::dbo::CredentialProfile;
+    // Audited through the profile info, which only tells whether secrets are set
+    let mut credential_profile_before : Option<serde_json::Value> = None;
+    match models::dbo::CredentialProfile::by_name(&new_credential_profile.name, &connection) {
+        Some(old_credential_profile) => {
+            credential_profile_before = Some(json!(credential_profile_info(&old_credential_profile, &connection)));
+            let updated_credential_profile = models::dbo::CredentialProfile {
+                id: old_credential_profile.id,
+                name: new_credential_profile.name,
+                snmp_version: new_credential_profile.snmp_version,
+                community: new_credential_profile.community,
+                username: new_credential_profile.username,
+                auth_protocol: new_credential_profile.auth_protocol,
+                auth_password: new_credential_profile.auth_password,
+                priv_protocol: new_credential_profile.priv_protocol,
+                priv_password: new_credential_profile.priv_password,
+            };
+            updated_credential_profile.update(&connection)?;
+            log_info!("credential profile updated", "route" => "PUT /credentialprofile", "profile" => updated_credential_profile.name, "by" => auth.token.name);
+            credential_profile = updated_credential_profile;
+        },
+        None => {
+            credential_profile = models::dbo::CredentialProfile::create(&new_credential_profile, &connection)?;
+            log_info!("credential profile created", "route" => "PUT /credentialprofile", "profile" => credential_profile.name, "by" => auth.token.name);
+        }
+    }
+    let credential_profile_info = credential_profile_info(&credential_profile, &connection);
+    utilities::audit::record(&connection, &auth.token, utilities::audit::ENTITY_CREDENTIAL_PROFILE, &credential_profile_info.name, credential_profile_before, Some(json!(credential_profile_info)));
+    return Ok(json::Json(credential_profile_info));
+}
+
+#[delete("/<name>")]
+pub fn credential_profile_delete(auth: Authorized<AdminScope>, connection: db::Connection, name: String) -> Result<json::Json<models::json::CredentialProfileInfo>, utilities::errors::ApiError> {
+    let credential_profile = match models::dbo::CredentialProfile::by_name(&name, &connection) {
+        Some(credential_profile) => credential_profile,
+        None => { return Err(utilities::errors::ApiError::NotFound(format!("credential profile {} not found", name))); }
+    };
+    let credential_profile_info = credential_profile_info(&credential_profile, &connection);
+    if credential_profile_info.device_count > 0 {
+        return Err(utilities::errors::ApiError::Conflict(format!("credential profile {} is still used by {} devices", name, credential_profile_info.device_count)));
+    }
+    credential_profile.delete(&connection)?;
+    log_info!("credential profile deleted", "route" => "DELETE /credentialprofile", "profile" => name, "by" => auth.token.name);
+    utilities::audit::record(&connection, &auth.token, utilities::audit::ENTITY_CREDENTIAL_PROFILE, &name, Some(json!(credential_profile_info)), None);
+    return Ok(json::Json(credential_profile_info));
+}
@@ -1,108 +1,293 @@
 extern crate rocket_contrib;
 use models;
 use db;
-use rocket::{get, put};
+use rocket::{get, put, patch, delete};
+use diesel::pg::PgConnection;
 use rocket_contrib::json;
 use std::sync::{Arc,Mutex};
+use std::collections::HashMap;
 use rocket::State;
 use utilities;
+use utilities::auth::{Authorized, AuthenticatedToken, AdminScope, ReadScope, ReportScope};
 
-#[get("/")]
-pub fn device_list(connection: db::Connection) -> json::Json<Vec<models::dbo::Device>> {
-    return json::Json(models::dbo::Device::all(&connection));
+#[get("/?<name>&<domain>&<os_info>&<base_mac>&<polling>&<sort>&<order>&<limit>&<offset>")]
+pub fn device_list(_auth: Authorized<ReadScope>, connection: db::Connection, name: Option<String>, domain: Option<String>, os_info: Option<String>, base_mac: Option<String>, polling: Option<bool>, sort: Option<String>, order: Option<String>, limit: Option<i64>, offset: Option<i64>) -> Result<utilities::pagination::Page<models::dbo::Device>, utilities::errors::ApiError> {
+    let pagination = utilities::pagination::pagination(sort, order, limit, offset, &models::dbo::DEVICE_SORT_FIELDS)?;
+    let device_filter = models::dbo::DeviceFilter {
+        name_prefix: name,
+        dns_domain_prefix: domain,
+        os_info_contains: os_info,
+        base_mac: base_mac,
+        polling_enabled: polling,
+    };
+    let (devices, total) = models::dbo::Device::search(&device_filter, &pagination, &connection)?;
+    return Ok(utilities::pagination::Page { items: devices, total: total });
 }
 
-#[put("/", data = "<device_json>")]
-pub fn device_create_or_modify(connection: db::Connection, device_json: rocket_contrib::json::Json<models::dbo::NewDevice>, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>) -> Option<json::Json<models::dbo::Device>> {
-    let mut device : models::dbo::Device;
-    if let Some(old_device) = models::dbo::Device::find_by_hostname_and_domain_name(&connection, &device_json.name, &device_json.dns_domain) {
-        let device_fqdn = format!("{}.{}", old_device.name, old_device.dns_domain);
-        let mut changed = false;
-        device = old_device;
-        if device.polling_enabled != device_json.polling_enabled {
+fn device_by_id(id: i32, connection: &PgConnection) -> Result<models::dbo::Device, utilities::errors::ApiError> {
+    match models::dbo::Device::by_id(id, connection) {
+        Some(device) => {
+            return Ok(device);
+        },
+        None => {
+            return Err(utilities::errors::ApiError::NotFound(format!("device {} not found", id)));
+        }
+    }
+}
+
+fn device_by_fqdn(fqdn: &String, connection: &PgConnection) -> Result<models::dbo::Device, utilities::errors::ApiError> {
+    match models::dbo::Device::find_by_fqdn(connection, fqdn) {
+        Some(device) => {
+            return Ok(device);
+        },
+        None => {
+            return Err(utilities::errors::ApiError::NotFound(format!("device {} not found", fqdn)));
+        }
+    }
+}
+
+struct DeviceChange {
+    device: models::dbo::Device,
+    // Published by the route once the transaction has committed
+    events: Vec<models::events::Event>,
+}
+
+fn publish_events(msgbus: &Arc<Mutex<utilities::msgbus::MessageBus>>, events: Vec<models::events::Event>) {
+    if let Ok(ref mut msgbus) = msgbus.lock() {
+        for event in events.into_iter() {
+            msgbus.event(event);
+        }
+    }
+}
+
+// Applies only the fields present in the patch. Attribute changes raise events, credential changes never do.
+// Runs inside the caller's transaction, so a failing credential store undoes the attribute changes too.
+fn modify_device(token: &AuthenticatedToken, connection: &PgConnection, mut device: models::dbo::Device, device_patch: &models::json::DevicePatch, credential_cipher: &utilities::credentials::CredentialCipher, route: &str) -> Result<DeviceChange, utilities::errors::ApiError> {
+    let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
+    // Resolved first so that an unknown profile rejects the request before any event goes out
+    let credential_profile_id : Option<Option<i32>> = match device_patch.credential_profile {
+        Some(Some(ref credential_profile)) => Some(Some(utilities::credentials::credential_profile_id(credential_profile, connection)?)),
+        Some(None) => Some(None),
+        None => None,
+    };
+    let device_before = json!(device);
+    let mut events : Vec<models::events::Event> = Vec::new();
+    let mut changed = false;
+    if let Some(polling_enabled) = device_patch.polling_enabled {
+        if device.polling_enabled != polling_enabled {
             let event = models::events::Event::device_polling_changed_event(
-                &device_fqdn, device.polling_enabled, device_json.polling_enabled);
-            if let Ok(ref mut msgbus) = msgbus.lock() {
-                msgbus.event(event);
-            }
+                &device_fqdn, device.polling_enabled, polling_enabled);
+            events.push(event);
             changed = true;
-            device.polling_enabled = device_json.polling_enabled.clone();
+            device.polling_enabled = polling_enabled;
         }
-        if device.os_info != device_json.os_info {
+    }
+    if let Some(ref os_info) = device_patch.os_info {
+        if device.os_info != *os_info {
             let event = models::events::Event::device_os_info_changed_event(
-                &device_fqdn, &device.os_info, &device_json.os_info);
-            if let Ok(ref mut msgbus) = msgbus.lock() {
-                msgbus.event(event);
-            }
+                &device_fqdn, &device.os_info, os_info);
+            events.push(event);
             changed = true;
-            device.os_info = device_json.os_info.clone();
+            device.os_info = os_info.clone();
         }
-        if device.base_mac != device_json.base_mac {
+    }
+    if let Some(ref base_mac) = device_patch.base_mac {
+        if device.base_mac != *base_mac {
             let event = models::events::Event::device_base_mac_changed_event(
-                &device_fqdn, &device.base_mac, &device_json.base_mac);
-            if let Ok(ref mut msgbus) = msgbus.lock() {
-                msgbus.event(event);
-            }
+                &device_fqdn, &device.base_mac, base_mac);
+            events.push(event);
             changed = true;
-            device.base_mac = device_json.base_mac.clone();
+            device.base_mac = base_mac.clone();
         }
-        if device.snmp_community != device_json.snmp_community {
-            // TODO: this MUST NOT raise an event!
+    }
+    if let Some(credential_profile_id) = credential_profile_id {
+        if device.credential_profile_id != credential_profile_id {
+            // Credential related like the community, so no event
             changed = true;
-            device.snmp_community = device_json.snmp_community.clone();
+            device.credential_profile_id = credential_profile_id;
         }
-        if changed {
-            if let Err(_) = device.update(&connection) {
-                // TODO: return 500
-                return None;
-            }
+    }
+    if changed {
+        if let Err(error) = device.update(connection) {
+            log_error!("failed to update device", "route" => route, "fqdn" => device_fqdn, "error" => error);
+            return Err(utilities::errors::ApiError::from(error));
         }
-    } else {
-        if let Ok(created_device) = models::dbo::Device::create(&device_json, &connection) {
-            let device_fqdn = format!("{}.{}", created_device.name, created_device.dns_domain);
-            if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_weathermap_cache(); }
-            device = created_device;
-            let event = models::events::Event::device_created_event(&device_fqdn);
-            if let Ok(ref mut msgbus) = msgbus.lock() {
-                msgbus.event(event);
-            }
-        } else {
-            // TODO: return 500
-            return None;
+        log_info!("device updated", "route" => route, "fqdn" => device_fqdn);
+        utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE, &device_fqdn, Some(device_before), Some(json!(device)));
+    }
+    if let Some(ref snmp_community) = device_patch.snmp_community {
+        // Deliberately without an event, subscribers must never see credentials
+        if utilities::credentials::store_snmp_community(&device, snmp_community, credential_cipher, connection)? {
+            log_info!("device SNMP community updated", "route" => route, "fqdn" => device_fqdn);
         }
     }
-    return Some(json::Json(device));
+    return Ok(DeviceChange {
+        device: device,
+        events: events,
+    });
+}
+
+fn create_device(token: &AuthenticatedToken, connection: &PgConnection, device_input: &models::json::DeviceInput, credential_cipher: &utilities::credentials::CredentialCipher) -> Result<DeviceChange, utilities::errors::ApiError> {
+    let credential_profile_id : Option<i32> = match device_input.credential_profile {
+        Some(ref credential_profile) => Some(utilities::credentials::credential_profile_id(credential_profile, connection)?),
+        None => None,
+    };
+    match models::dbo::Device::create(&device_input.new_device(credential_profile_id), connection) {
+        Ok(device) => {
+            let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
+            utilities::credentials::store_snmp_community(&device, &device_input.snmp_community, credential_cipher, connection)?;
+            log_info!("device created", "route" => "PUT /device", "fqdn" => device_fqdn);
+            utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE, &device_fqdn, None, Some(json!(device)));
+            return Ok(DeviceChange {
+                events: vec![models::events::Event::device_created_event(&device_fqdn)],
+                device: device,
+            });
+        },
+        Err(error) => {
+            log_error!("failed to create device", "route" => "PUT /device", "fqdn" => format!("{}.{}", device_input.name, device_input.dns_domain), "error" => error);
+            return Err(utilities::errors::ApiError::from(error));
+        }
+    }
+}
+
+fn remove_device(token: &AuthenticatedToken, connection: &PgConnection, device: models::dbo::Device, cache_controller: &Arc<Mutex<utilities::cache::CacheController>>, msgbus: &Arc<Mutex<utilities::msgbus::MessageBus>>, route: &str) -> Result<models::dbo::Device, utilities::errors::ApiError> {
+    let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
+    if let Err(error) = device.delete(connection) {
+        log_error!("failed to delete device", "route" => route, "fqdn" => device_fqdn, "error" => error);
+        return Err(utilities::errors::ApiError::from(error));
+    }
+    log_info!("device deleted", "route" => route, "fqdn" => device_fqdn);
+    utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE, &device_fqdn, Some(json!(device)), None);
+    if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_topology_cache(); }
+    let event = models::events::Event::device_deleted_event(&device_fqdn);
+    if let Ok(ref mut msgbus) = msgbus.lock() {
+        msgbus.event(event);
+    }
+    return Ok(device);
+}
+
+// Replaces every field, PATCH /device/<id> changes only the supplied ones
+#[put("/", data = "<device_json>")]
+pub fn device_create_or_modify(auth: Authorized<AdminScope>, connection: db::Connection, device_json: rocket_contrib::json::Json<models::json::DeviceInput>, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, credential_cipher: State<utilities::credentials::CredentialCipher>) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    if let Some(old_device) = models::dbo::Device::find_by_hostname_and_domain_name(&connection, &device_json.name, &device_json.dns_domain) {
+        let device_change = db::transaction(&connection, false, || modify_device(&auth.token, &connection, old_device, &device_json.patch(), &credential_cipher, "PUT /device"))?;
+        publish_events(msgbus.inner(), device_change.events);
+        return Ok(json::Json(device_change.device));
+    }
+
+    let device_change = db::transaction(&connection, false, || create_device(&auth.token, &connection, &device_json, &credential_cipher))?;
+    if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_topology_cache(); }
+    publish_events(msgbus.inner(), device_change.events);
+    return Ok(json::Json(device_change.device));
 }
 
 #[delete("/", data = "<device_json>")]
-pub fn device_delete(connection: db::Connection, device_json: rocket_contrib::json::Json<models::dbo::NewDevice>, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>) -> Option<json::Json<models::dbo::Device>> {
+pub fn device_delete(auth: Authorized<AdminScope>, connection: db::Connection, device_json: rocket_contrib::json::Json<models::dbo::NewDevice>, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
     if let Some(old_device) = models::dbo::Device::find_by_hostname_and_domain_name(&connection, &device_json.name, &device_json.dns_domain) {
-        if let Err(d) = old_device.delete(&connection) {
-            println!("{}", d);
-            // TODO: return 500
-            return None;
-        } else {
-            let device_fqdn = format!("{}.{}", old_device.name, old_device.dns_domain);
-            if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_weathermap_cache(); }
-            let event = models::events::Event::device_deleted_event(&device_fqdn);
-            if let Ok(ref mut msgbus) = msgbus.lock() {
-                msgbus.event(event);
-            }
-            return Some(json::Json(old_device));
-        }
+        return Ok(json::Json(remove_device(&auth.token, &connection, old_device, cache_controller.inner(), msgbus.inner(), "DELETE /device")?));
     } else {
-        return None;
+        return Err(utilities::errors::ApiError::NotFound(format!("device {}.{} not found", device_json.name, device_json.dns_domain)));
+    }
+}
+
+#[get("/<id>")]
+pub fn device_get(_auth: Authorized<ReadScope>, connection: db::Connection, id: i32) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    return Ok(json::Json(device_by_id(id, &connection)?));
+}
+
+#[get("/by-fqdn/<fqdn>")]
+pub fn device_get_by_fqdn(_auth: Authorized<ReadScope>, connection: db::Connection, fqdn: String) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    return Ok(json::Json(device_by_fqdn(&fqdn, &connection)?));
+}
+
+#[patch("/<id>", data = "<device_patch>")]
+pub fn device_patch(auth: Authorized<AdminScope>, connection: db::Connection, id: i32, device_patch: json::Json<models::json::DevicePatch>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, credential_cipher: State<utilities::credentials::CredentialCipher>) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    let device = device_by_id(id, &connection)?;
+    let device_change = db::transaction(&connection, false, || modify_device(&auth.token, &connection, device, &device_patch, &credential_cipher, "PATCH /device/<id>"))?;
+    publish_events(msgbus.inner(), device_change.events);
+    return Ok(json::Json(device_change.device));
+}
+
+#[patch("/by-fqdn/<fqdn>", data = "<device_patch>")]
+pub fn device_patch_by_fqdn(auth: Authorized<AdminScope>, connection: db::Connection, fqdn: String, device_patch: json::Json<models::json::DevicePatch>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, credential_cipher: State<utilities::credentials::CredentialCipher>) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    let device = device_by_fqdn(&fqdn, &connection)?;
+    let device_change = db::transaction(&connection, false, || modify_device(&auth.token, &connection, device, &device_patch, &credential_cipher, "PATCH /device/by-fqdn/<fqdn>"))?;
+    publish_events(msgbus.inner(), device_change.events);
+    return Ok(json::Json(device_change.device));
+}
+
+#[delete("/<id>")]
+pub fn device_delete_by_id(auth: Authorized<AdminScope>, connection: db::Connection, id: i32, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    let device = device_by_id(id, &connection)?;
+    return Ok(json::Json(remove_device(&auth.token, &connection, device, cache_controller.inner(), msgbus.inner(), "DELETE /device/<id>")?));
+}
+
+#[delete("/by-fqdn/<fqdn>")]
+pub fn device_delete_by_fqdn(auth: Authorized<AdminScope>, connection: db::Connection, fqdn: String, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>) -> Result<json::Json<models::dbo::Device>, utilities::errors::ApiError> {
+    let device = device_by_fqdn(&fqdn, &connection)?;
+    return Ok(json::Json(remove_device(&auth.token, &connection, device, cache_controller.inner(), msgbus.inner(), "DELETE /device/by-fqdn/<fqdn>")?));
+}
+
+// Credentials are kept out of the Device representation, only pollers get them and only from here
+#[get("/credentials")]
+pub fn device_credentials(_auth: Authorized<ReportScope>, connection: db::Connection, credential_cipher: State<utilities::credentials::CredentialCipher>) -> json::Json<Vec<models::json::DeviceCredentialInfo>> {
+    let mut device_credential_infos : Vec<models::json::DeviceCredentialInfo> = Vec::new();
+    let mut credential_profile_names : HashMap<i32, String> = HashMap::new();
+    for credential_profile in models::dbo::CredentialProfile::all(&connection).into_iter() {
+        credential_profile_names.insert(credential_profile.id, credential_profile.name);
+    }
+    for monitored in models::dbo::Device::monitored(&connection).iter() {
+        let device_fqdn = format!("{}.{}", monitored.name, monitored.dns_domain);
+        let snmp_community : Option<String> = match models::dbo::DeviceCredential::by_device(monitored.id, &connection) {
+            Some(device_credential) => {
+                match credential_cipher.decrypt_optional(&device_credential.snmp_community) {
+                    Ok(snmp_community) => snmp_community,
+                    Err(error) => {
+                        log_error!("failed to decrypt SNMP community", "route" => "GET /device/credentials", "fqdn" => device_fqdn, "error" => error);
+                        None
+                    }
+                }
+            },
+            None => None,
+        };
+        let credential_profile : Option<String> = match monitored.credential_profile_id {
+            Some(credential_profile_id) => credential_profile_names.get(&credential_profile_id).cloned(),
+            None => None,
+        };
+        device_credential_infos.push(models::json::DeviceCredentialInfo { fqdn: device_fqdn, snmp_community: snmp_community, credential_profile: credential_profile });
     }
+    return json::Json(device_credential_infos);
 }
 
 #[get("/monitor")]
-pub fn monitored_device_list(connection: db::Connection, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, runtime_info: State<Arc<Mutex<models::internal::RuntimeInfo>>>) -> json::Json<models::json::DeviceMonitorResponse> {
+pub fn monitored_device_list(_auth: Authorized<ReportScope>, connection: db::Connection, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, runtime_info: State<Arc<Mutex<models::internal::RuntimeInfo>>>, credential_cipher: State<utilities::credentials::CredentialCipher>) -> json::Json<models::json::DeviceMonitorResponse> {
     let mut dmi : Vec<models::json::DeviceMonitorInfo> = Vec::new();
+    let mut credential_profiles_by_id : HashMap<i32, models::dbo::CredentialProfile> = HashMap::new();
+    for credential_profile in models::dbo::CredentialProfile::all(&connection).into_iter() {
+        credential_profiles_by_id.insert(credential_profile.id, credential_profile);
+    }
+    let mut credential_profiles : HashMap<String, models::json::SnmpCredentials> = HashMap::new();
     for monitored in models::dbo::Device::monitored(&connection).iter() {
+        let device_fqdn = format!("{}.{}", monitored.name, monitored.dns_domain);
+        let mut credential_profile_name : Option<String> = None;
+        if let Some(credential_profile) = monitored.credential_profile_id.and_then(|credential_profile_id| credential_profiles_by_id.get(&credential_profile_id)) {
+            if !credential_profiles.contains_key(&credential_profile.name) {
+                match utilities::credentials::snmp_credentials(credential_profile, &credential_cipher) {
+                    Ok(snmp_credentials) => {
+                        credential_profiles.insert(credential_profile.name.clone(), snmp_credentials);
+                    },
+                    Err(error) => {
+                        log_error!("failed to decrypt credential profile", "route" => "GET /device/monitor", "profile" => credential_profile.name, "error" => error);
+                    }
+                }
+            }
+            if credential_profiles.contains_key(&credential_profile.name) {
+                credential_profile_name = Some(credential_profile.name.clone());
+            }
+        }
         if let Ok(ref mut imds) = imds.lock() {
-            let device_fqdn = format!("{}.{}", monitored.name, monitored.dns_domain);
             if let Some(imds_device) = imds.get_device(&device_fqdn) {
-                dmi.push(models::json::DeviceMonitorInfo { fqdn: device_fqdn, up: imds_device.up });
+                dmi.push(models::json::DeviceMonitorInfo { fqdn: device_fqdn, up: imds_device.up, credential_profile: credential_profile_name });
             }
         }
     }
@@ -112,12 +297,31 @@ pub fn monitored_device_list(connection: db::Connection, imds: State<Arc<Mutex<u
     } else {
         state_id = 0;
     }
-    return json::Json(models::json::DeviceMonitorResponse { state_id: state_id, devices: dmi });
+    return json::Json(models::json::DeviceMonitorResponse { state_id: state_id, devices: dmi, credential_profiles: credential_profiles });
 }
 
 #[put("/monitor", data = "<device_monitor_report>")]
-pub fn monitored_device_report(connection: db::Connection, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, device_monitor_report : json::Json<models::json::DeviceMonitorReport>) {
+pub fn monitored_device_report(_auth: Authorized<ReportScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, device_monitor_report : json::Json<models::json::DeviceMonitorReport>) -> json::Json<models::json::MonitorReportResult> {
     if let Ok(ref mut imds) = imds.lock() {
-        imds.report_device(&connection, device_monitor_report.into_inner());
+        return json::Json(imds.report_device(device_monitor_report.into_inner()));
+    }
+    return json::Json(models::json::MonitorReportResult { fqdn: device_monitor_report.fqdn.clone(), accepted: false, unknown_device: false, unknown_if_indexes: Vec::new() });
+}
+
+#[put("/monitor/batch", data = "<device_monitor_reports>")]
+pub fn monitored_device_report_batch(_auth: Authorized<ReportScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, device_monitor_reports : json::Json<Vec<models::json::DeviceMonitorReport>>) -> Result<json::Json<Vec<models::json::MonitorReportResult>>, utilities::errors::ApiError> {
+    let mut results : Vec<models::json::MonitorReportResult> = Vec::new();
+    // Rejecting every item would look like a normal resync request to the poller
+    match imds.lock() {
+        Ok(ref mut imds) => {
+            for device_monitor_report in device_monitor_reports.into_inner().into_iter() {
+                results.push(imds.report_device(device_monitor_report));
+            }
+        },
+        Err(_) => {
+            log_error!("IMDS lock poisoned, rejecting device report batch", "count" => device_monitor_reports.len());
+            return Err(utilities::errors::ApiError::Unavailable("in-memory metrics store is unavailable".to_string()));
+        }
     }
+    return Ok(json::Json(results));
 }
@@ -2,32 +2,150 @@ extern crate rocket_contrib;
 use models;
 use db;
 use rocket::{get, put};
+use rocket::request::LenientForm;
+use diesel::pg::PgConnection;
+use rocket_contrib::json;
 use std::sync::{Arc,Mutex};
-use std::collections::{HashSet,HashMap};
+use std::collections::{HashSet,HashMap,BTreeMap};
+use serde_json;
 use rocket::State;
 use utilities;
+use utilities::auth::{Authorized, AuthenticatedToken, DiscoveryScope};
 
-#[put("/device", data = "<discovery_json>")]
-pub fn discovery_device(discovery_json: rocket_contrib::json::Json<models::json::DiscoveredDevice>, connection: db::Connection, metric_miss_cache: State<Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>>) {
-    let discovered_device : &models::json::DiscoveredDevice = &discovery_json.into_inner();
-    let discovered_device_interfaces : &HashMap<String, models::json::DiscoveredInterface> = &discovered_device.interfaces;
+// Interfaces are compared by id, so that a rename shows up as one, not as a removal and an addition
+fn interfaces_diff(interfaces_before: &serde_json::Value, interfaces_after: &serde_json::Value, device_diff: &mut models::json::DiscoveryDeviceDiff) {
+    let by_id = |interfaces: &serde_json::Value| -> HashMap<i64, (String, serde_json::Value)> {
+        let mut interfaces_by_id : HashMap<i64, (String, serde_json::Value)> = HashMap::new();
+        if let Some(interfaces) = interfaces.as_object() {
+            for (name, interface) in interfaces.iter() {
+                if let Some(id) = interface.get("id").and_then(|id| id.as_i64()) {
+                    interfaces_by_id.insert(id, (name.clone(), interface.clone()));
+                }
+            }
+        }
+        return interfaces_by_id;
+    };
+    let before_interfaces = by_id(interfaces_before);
+    let after_interfaces = by_id(interfaces_after);
+    for (id, &(ref name, ref interface_after)) in after_interfaces.iter() {
+        match before_interfaces.get(id) {
+            Some(&(ref old_name, ref interface_before)) => {
+                if old_name != name {
+                    device_diff.interfaces_renamed.insert(old_name.clone(), name.clone());
+                }
+                let interface_changes = utilities::audit::field_changes(interface_before, interface_after);
+                if !interface_changes.is_empty() {
+                    device_diff.interfaces_updated.insert(name.clone(), interface_changes);
+                }
+            },
+            None => {
+                device_diff.interfaces_added.push(name.clone());
+            }
+        }
+    }
+    for (id, &(ref name, _)) in before_interfaces.iter() {
+        if !after_interfaces.contains_key(id) {
+            device_diff.interfaces_removed.push(name.clone());
+        }
+    }
+    device_diff.interfaces_added.sort();
+    device_diff.interfaces_removed.sort();
+}
+
+fn links_diff(device_fqdn: &String, links_before: &serde_json::Value, links_after: &serde_json::Value, diff: &mut models::json::DiscoveryLinksDiff) {
+    let empty = serde_json::Map::new();
+    let before_links = links_before.as_object().unwrap_or(&empty);
+    for (name, peer_after) in links_after.as_object().unwrap_or(&empty).iter() {
+        let peer_before = before_links.get(name).cloned().unwrap_or(serde_json::Value::Null);
+        if peer_before == *peer_after { continue; }
+        let interface_key = format!("{} {}", device_fqdn, name);
+        match peer_after.as_str() {
+            Some(peer_after) => { diff.links_set.insert(interface_key, peer_after.to_string()); },
+            None => { diff.links_cleared.push(interface_key); }
+        }
+    }
+}
+
+struct DeviceIngest {
+    diff: models::json::DiscoveryDeviceDiff,
+    topology_changed: bool,
+    // Published by the route once the transaction has committed
+    events: Vec<models::events::Event>,
+}
 
+// Runs inside the discovery transaction, any error rolls back the whole report
+// A base MAC is only trusted to identify a renamed device when a single device has it and that
+// device stopped reporting under its old name, anything else is logged and added as a new device.
+fn renamed_device<F>(discovered_device: &models::json::DiscoveredDevice, discovered_fqdn: &String, connection: &PgConnection, is_reporting: &F) -> Result<Option<models::dbo::Device>, utilities::errors::ApiError> where F: Fn(&String) -> bool {
+    let base_mac = match discovered_device.base_mac {
+        Some(ref base_mac) if !base_mac.is_empty() => base_mac,
+        _ => { return Ok(None); }
+    };
+    let mut candidates = models::dbo::Device::all_by_base_mac(base_mac, connection)?;
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    if candidates.len() > 1 {
+        let fqdns : Vec<String> = candidates.iter().map(|candidate| format!("{}.{}", candidate.name, candidate.dns_domain)).collect();
+        log_warn!("base MAC shared by several devices, not treating as a rename", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "base_mac" => base_mac, "devices" => fqdns.join(","));
+        return Ok(None);
+    }
+    let candidate = candidates.remove(0);
+    let old_fqdn = format!("{}.{}", candidate.name, candidate.dns_domain);
+    if is_reporting(&old_fqdn) {
+        log_warn!("base MAC belongs to a device that is still reporting, not treating as a rename", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "base_mac" => base_mac, "old_fqdn" => old_fqdn);
+        return Ok(None);
+    }
+    return Ok(Some(candidate));
+}
+
+fn ingest_device<F>(token: &AuthenticatedToken, discovered_device: &models::json::DiscoveredDevice, connection: &PgConnection, credential_cipher: &utilities::credentials::CredentialCipher, dry_run: bool, is_reporting: &F) -> Result<DeviceIngest, utilities::errors::ApiError> where F: Fn(&String) -> bool {
+    let discovered_device_interfaces : &HashMap<String, models::json::DiscoveredInterface> = &discovered_device.interfaces;
+    let discovered_fqdn = format!("{}.{}", discovered_device.name, discovered_device.dns_domain);
+    let credential_profile_id : Option<i32> = match discovered_device.credential_profile {
+        Some(ref credential_profile) => Some(utilities::credentials::credential_profile_id(credential_profile, connection)?),
+        None => None,
+    };
     let device : models::dbo::Device;
-    let existing_device = models::dbo::Device::find_by_hostname_and_domain_name(&connection, &discovered_device.name, &discovered_device.dns_domain);
+    let mut topology_changed = false;
+    let mut events : Vec<models::events::Event> = Vec::new();
+    let mut device_before : Option<serde_json::Value> = None;
+    let mut interfaces_before : Option<serde_json::Value> = None;
+    let mut renamed_from : Option<String> = None;
+    let existing_device = match models::dbo::Device::find_by_hostname_and_domain_name(connection, &discovered_device.name, &discovered_device.dns_domain) {
+        Some(existing_device) => Some(existing_device),
+        // A known base MAC under an unknown name is the same device renamed, it keeps its links and position
+        None => renamed_device(discovered_device, &discovered_fqdn, connection, is_reporting)?,
+    };
     match existing_device {
         Some(mut existing_device) => {
+            device_before = Some(json!(existing_device));
+            interfaces_before = Some(utilities::audit::interfaces_state(&existing_device.interfaces(connection)));
+            if existing_device.name != discovered_device.name || existing_device.dns_domain != discovered_device.dns_domain {
+                // Only reachable through the base MAC lookup, so it is always set here
+                let base_mac = discovered_device.base_mac.clone().unwrap_or_default();
+                let old_fqdn = format!("{}.{}", existing_device.name, existing_device.dns_domain);
+                log_info!("discovered device renamed", "route" => "PUT /discovery/device", "old_fqdn" => old_fqdn, "fqdn" => discovered_fqdn, "base_mac" => base_mac, "dry_run" => dry_run);
+                events.push(models::events::Event::device_renamed_event(&old_fqdn, &discovered_fqdn, &base_mac));
+                existing_device.name = discovered_device.name.clone();
+                existing_device.dns_domain = discovered_device.dns_domain.clone();
+                topology_changed = true;
+                renamed_from = Some(old_fqdn);
+            }
             // TODO: attr compare, event if change except for snmp com
             existing_device.base_mac = discovered_device.base_mac.clone();
             existing_device.os_info = discovered_device.os_info.clone();
-            existing_device.snmp_community = discovered_device.snmp_community.clone();
-            match existing_device.update(&connection) {
+            if credential_profile_id.is_some() {
+                existing_device.credential_profile_id = credential_profile_id;
+            }
+            match existing_device.update(connection) {
                 Ok(_) => {
-                    // TODO: Log update?
+                    log_debug!("discovered device updated", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "dry_run" => dry_run);
                     device = existing_device;
                 },
-                Err(_) => {
-                    // TODO: sane logging / return
-                    return;
+                Err(error) => {
+                    log_error!("failed to update discovered device", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "error" => error);
+                    return Err(utilities::errors::ApiError::from(error));
                 }
             }
         },
@@ -36,47 +154,57 @@ pub fn discovery_device(discovery_json: rocket_contrib::json::Json<models::json:
             let new_device = models::dbo::NewDevice {
                 name: discovered_device.name.clone(),
                 dns_domain: discovered_device.dns_domain.clone(),
-                snmp_community: discovered_device.snmp_community.clone(),
                 base_mac: discovered_device.base_mac.clone(),
                 os_info: discovered_device.os_info.clone(),
                 polling_enabled: None,
+                credential_profile_id: credential_profile_id,
             };
-            match models::dbo::Device::create(&new_device, &connection) {
+            match models::dbo::Device::create(&new_device, connection) {
                 Ok(created_device) => {
+                    log_info!("discovered device created", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "dry_run" => dry_run);
+                    topology_changed = true;
                     device = created_device;
                 },
-                Err(_) => {
-                    // TODO: sane logging / return
-                    return;
+                Err(error) => {
+                    log_error!("failed to create discovered device", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "error" => error);
+                    return Err(utilities::errors::ApiError::from(error));
                 }
             }
         }
     }
+    if utilities::credentials::store_snmp_community(&device, &discovered_device.snmp_community, credential_cipher, connection)? {
+        log_debug!("discovered device SNMP community updated", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "dry_run" => dry_run);
+    }
     
-    let current_interfaces : Vec<models::dbo::Interface> = device.interfaces(&connection);
-    let mut found_interface_names : HashSet<String> = HashSet::new();
-    for (_key, interface) in discovered_device_interfaces.iter() {
-        found_interface_names.insert(interface.name.clone());
-        let mut selected_interface : Option<&models::dbo::Interface> = None;
-        for current_interface in current_interfaces.iter() {
-            if interface.name == current_interface.name {
-                selected_interface = Some(current_interface);
-                break;
-            }
-        }
-        match selected_interface {
-            Some(selected_interface) => {
-                let mut updated_interface : models::dbo::Interface = (*selected_interface).clone();
+    let current_interfaces : Vec<models::dbo::Interface> = device.interfaces(connection);
+    // Sorted so that ambiguous loose matches resolve the same way on every report
+    let mut sorted_discovered_interfaces : Vec<&models::json::DiscoveredInterface> = discovered_device_interfaces.values().collect();
+    sorted_discovered_interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    let interface_matches = utilities::interfacematch::match_interfaces(&sorted_discovered_interfaces, &current_interfaces);
+    let mut matched_interface_ids : HashSet<i32> = HashSet::new();
+    for (interface, interface_match) in sorted_discovered_interfaces.iter().zip(interface_matches.iter()) {
+        match *interface_match {
+            Some(ref interface_match) => {
+                matched_interface_ids.insert(interface_match.interface.id);
+                let mut updated_interface : models::dbo::Interface = interface_match.interface.clone();
                 updated_interface.index = interface.index;
                 updated_interface.interface_type = interface.interface_type.clone();
                 updated_interface.name = interface.name.clone();
                 updated_interface.alias = interface.alias.clone();
                 updated_interface.description = interface.description.clone();
-                match updated_interface.update(&connection) {
-                    Ok(_) => {},
-                    Err(_) => {
-                        // TODO: logging, nonfatal
-                    }
+                if let Err(error) = updated_interface.update(connection) {
+                    log_error!("failed to update discovered interface", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => interface.name, "error" => error);
+                    return Err(utilities::errors::ApiError::from(error));
+                }
+                if interface_match.interface.name != interface.name {
+                    log_info!("discovered interface renamed", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "old_name" => interface_match.interface.name, "interface" => interface.name, "matched_by" => interface_match.matched_by, "dry_run" => dry_run);
+                    events.push(models::events::Event::interface_renamed_event(&discovered_fqdn, &interface_match.interface.name, &updated_interface, interface_match.matched_by));
+                    topology_changed = true;
+                }
+                // The topology graph resolves peers and aggregates by ifIndex
+                if interface_match.interface.index != interface.index {
+                    log_info!("discovered interface index changed", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => interface.name, "old_index" => interface_match.interface.index, "index" => interface.index, "dry_run" => dry_run);
+                    topology_changed = true;
                 }
             },
             None => {
@@ -89,11 +217,14 @@ pub fn discovery_device(discovery_json: rocket_contrib::json::Json<models::json:
                     interface_type: interface.interface_type.clone(),
                 };
                 
-                match models::dbo::Interface::create(&new_interface, &connection) {
+                match models::dbo::Interface::create(&new_interface, connection) {
                     Ok(_) => {
+                        log_info!("discovered interface created", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => interface.name, "dry_run" => dry_run);
+                        topology_changed = true;
                     },
-                    Err(_) => {
-                        // Todo, logging? :) this is nonfatal
+                    Err(error) => {
+                        log_error!("failed to create discovered interface", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => interface.name, "error" => error);
+                        return Err(utilities::errors::ApiError::from(error));
                     }
                 }
             }
@@ -101,64 +232,164 @@ pub fn discovery_device(discovery_json: rocket_contrib::json::Json<models::json:
     }
 
     for current_interface in current_interfaces.iter() {
-        if !found_interface_names.contains(&current_interface.name) {
-            match current_interface.delete(&connection) {
-                Ok(_) => {},
-                Err(_) => {
-                    // Todo, logging? nonfatal
+        if !matched_interface_ids.contains(&current_interface.id) {
+            match current_interface.delete(connection) {
+                Ok(_) => {
+                    log_info!("vanished interface deleted", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => current_interface.name, "dry_run" => dry_run);
+                    topology_changed = true;
+                },
+                Err(error) => {
+                    log_error!("failed to delete vanished interface", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => current_interface.name, "error" => error);
+                    return Err(utilities::errors::ApiError::from(error));
                 }
             }
         }
     }
 
+    // Aggregate membership refers to ifIndexes, so resolve it only after all interfaces exist
+    let refreshed_interfaces : Vec<models::dbo::Interface> = device.interfaces(connection);
+    let mut interface_ids_by_index : HashMap<i32, i32> = HashMap::new();
+    for refreshed_interface in refreshed_interfaces.iter() {
+        interface_ids_by_index.insert(refreshed_interface.index, refreshed_interface.id);
+    }
+    for (_key, interface) in discovered_device_interfaces.iter() {
+        let aggregate_interface : Option<i32> = match interface.aggregate_index {
+            Some(aggregate_index) => interface_ids_by_index.get(&aggregate_index).cloned(),
+            None => None,
+        };
+        for refreshed_interface in refreshed_interfaces.iter() {
+            if refreshed_interface.name != interface.name { continue; }
+            if refreshed_interface.aggregate_interface == aggregate_interface { break; }
+            let mut updated_interface : models::dbo::Interface = refreshed_interface.clone();
+            updated_interface.aggregate_interface = aggregate_interface;
+            if let Err(error) = updated_interface.update(connection) {
+                log_error!("failed to update interface aggregate", "route" => "PUT /discovery/device", "fqdn" => discovered_fqdn, "interface" => interface.name, "error" => error);
+                return Err(utilities::errors::ApiError::from(error));
+            }
+            break;
+        }
+    }
+
+    let device_after = json!(device);
+    let interfaces_after = utilities::audit::interfaces_state(&device.interfaces(connection));
+    let mut device_diff = models::json::DiscoveryDeviceDiff {
+        dry_run: dry_run,
+        device_fqdn: discovered_fqdn.clone(),
+        device_created: device_before.is_none(),
+        renamed_from: renamed_from,
+        device_changes: utilities::audit::field_changes(device_before.as_ref().unwrap_or(&serde_json::Value::Null), &device_after),
+        interfaces_added: Vec::new(),
+        interfaces_removed: Vec::new(),
+        interfaces_renamed: BTreeMap::new(),
+        interfaces_updated: BTreeMap::new(),
+    };
+    interfaces_diff(interfaces_before.as_ref().unwrap_or(&serde_json::Value::Null), &interfaces_after, &mut device_diff);
+
+    utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE, &discovered_fqdn, device_before, Some(device_after));
+    utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE_INTERFACES, &discovered_fqdn, interfaces_before, Some(interfaces_after));
+    return Ok(DeviceIngest {
+        diff: device_diff,
+        topology_changed: topology_changed,
+        events: events,
+    });
+}
+
+// The whole report is applied in one transaction. With dryRun=true it is rolled back and only
+// the computed diff is returned.
+#[put("/device?<options..>", data = "<discovery_json>")]
+pub fn discovery_device(auth: Authorized<DiscoveryScope>, options: LenientForm<models::json::DryRunOptions>, discovery_json: rocket_contrib::json::Json<models::json::DiscoveredDevice>, connection: db::Connection, metric_miss_cache: State<Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>>, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, credential_cipher: State<utilities::credentials::CredentialCipher>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Result<json::Json<models::json::DiscoveryDeviceDiff>, utilities::errors::ApiError> {
+    let discovered_device : &models::json::DiscoveredDevice = &discovery_json.into_inner();
+    let dry_run = options.dry_run.unwrap_or(false);
+    // An unusable IMDS counts as reporting, so no rename is made on guesswork
+    let is_reporting = |device_fqdn: &String| imds.lock().map(|imds| imds.is_reporting(device_fqdn)).unwrap_or(true);
+    let device_ingest = db::transaction(&connection, dry_run, || ingest_device(&auth.token, discovered_device, &connection, &credential_cipher, dry_run, &is_reporting))?;
+    let device_diff = device_ingest.diff;
+    let topology_changed = device_ingest.topology_changed;
+    if dry_run {
+        return Ok(json::Json(device_diff));
+    }
+
+    if let Some(ref old_fqdn) = device_diff.renamed_from {
+        if let Ok(ref mut imds) = imds.lock() {
+            imds.rename_device(old_fqdn, &device_diff.device_fqdn);
+        }
+    }
+    if let Ok(ref mut msgbus) = msgbus.lock() {
+        for event in device_ingest.events.into_iter() {
+            msgbus.event(event);
+        }
+    }
+
     // TODO: optimize: only invalidate metric miss cache if stuff changes
-    let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
+    let device_fqdn = device_diff.device_fqdn.clone();
     if let Ok(ref mut metric_miss_cache) = metric_miss_cache.inner().lock() {
         if !metric_miss_cache.miss_set.contains(&device_fqdn) { metric_miss_cache.miss_set.insert(device_fqdn); }
     }
+
+    if topology_changed {
+        if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_topology_cache(); }
+    }
+    return Ok(json::Json(device_diff));
 }
 
 // TODO: this might be better placed in an utility module or maybe in dbo logic?
-fn clear_connection(interface: &models::dbo::Interface, connection: &db::Connection) {
-    if interface.connected_interface.is_none() { return; }
+fn clear_connection(interface: &models::dbo::Interface, connection: &PgConnection) -> Result<bool, utilities::errors::ApiError> {
+    if interface.connected_interface.is_none() { return Ok(false); }
 
     let mut new_local_interface : models::dbo::Interface = interface.clone();
     new_local_interface.connected_interface = None;
-    match new_local_interface.update(&connection) {
-        Ok(_) => {},
-        Err(_) => {
-            // TODO: log?
+    match new_local_interface.update(connection) {
+        Ok(_) => {
+            return Ok(true);
+        },
+        Err(error) => {
+            log_error!("failed to clear interface connection", "interface_id" => interface.id, "interface" => interface.name, "error" => error);
+            return Err(utilities::errors::ApiError::from(error));
         }
     }
 }
 
-#[put("/links", data = "<links_json>")]
-pub fn discovery_links(
-    links_json: rocket_contrib::json::Json<models::json::LinkInfo>,
-    connection: db::Connection,
-    metric_miss_cache: State<Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>>,
-    cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>
-) {
-    let link_infos : &HashMap<String, Option<models::json::LinkPeerInfo>> = &links_json.interfaces;
-    let fqdn_splitted : Vec<&str> = links_json.device_fqdn.splitn(2, ".").collect();
+fn set_connection(interface: &models::dbo::Interface, peer_interface: &models::dbo::Interface, device_fqdn: &String, connection: &PgConnection) -> Result<(), utilities::errors::ApiError> {
+    // TBD: create link other way too? maybe not?
+    let mut new_local_interface : models::dbo::Interface = interface.clone();
+    new_local_interface.connected_interface = Some(peer_interface.id);
+    if let Err(error) = new_local_interface.update(connection) {
+        log_error!("failed to update link", "route" => "PUT /discovery/links", "fqdn" => device_fqdn, "interface" => interface.name, "error" => error);
+        return Err(utilities::errors::ApiError::from(error));
+    }
+    return Ok(());
+}
+
+// Runs inside the discovery transaction, any error rolls back the whole report
+fn ingest_links(token: &AuthenticatedToken, link_report: &models::json::LinkInfo, connection: &PgConnection, dry_run: bool) -> Result<(models::json::DiscoveryLinksDiff, bool), utilities::errors::ApiError> {
+    let link_infos : &HashMap<String, Option<models::json::LinkPeerInfo>> = &link_report.interfaces;
+    let fqdn_splitted : Vec<&str> = link_report.device_fqdn.splitn(2, ".").collect();
     if fqdn_splitted.len() != 2 {
-        // TODO: log?
-        return;
+        log_warn!("link report with malformed fqdn", "route" => "PUT /discovery/links", "fqdn" => link_report.device_fqdn);
+        return Err(utilities::errors::ApiError::BadRequest(format!("malformed device fqdn {}", link_report.device_fqdn)));
     }
 
     let local_device : models::dbo::Device;
-    let local_device_result = models::dbo::Device::find_by_hostname_and_domain_name(&connection, &fqdn_splitted[0].to_string(), &fqdn_splitted[1].to_string());
+    let local_device_result = models::dbo::Device::find_by_hostname_and_domain_name(connection, &fqdn_splitted[0].to_string(), &fqdn_splitted[1].to_string());
     match local_device_result {
         Some(local_device_result) => {
             local_device = local_device_result;
         },
         None => {
-            // TODO: log?
-            return;
+            log_warn!("link report for unknown device", "route" => "PUT /discovery/links", "fqdn" => link_report.device_fqdn);
+            return Err(utilities::errors::ApiError::NotFound(format!("device {} not found", link_report.device_fqdn)));
         }
     }
 
-    for local_interface in local_device.interfaces(&connection).iter() {
+    let mut links_diff_result = models::json::DiscoveryLinksDiff {
+        dry_run: dry_run,
+        device_fqdn: link_report.device_fqdn.clone(),
+        links_set: BTreeMap::new(),
+        links_cleared: Vec::new(),
+    };
+    let links_before = utilities::audit::links_state(&local_device, connection);
+    let mut links_changed = false;
+    for local_interface in local_device.interfaces(connection).iter() {
         let peer_interface_info : &models::json::LinkPeerInfo;
         match link_infos.get(&local_interface.name) {
             Some(peer_interface_info_opt) => {
@@ -168,27 +399,27 @@ pub fn discovery_links(
                     },
                     None => {
                         // TBD, should we clear peer connection? This must respect stability.
-                        if !links_json.topology_stable { clear_connection(local_interface, &connection); }
+                        if !link_report.topology_stable && clear_connection(local_interface, connection)? { links_changed = true; }
                         continue;
                     }
                 }
             },
             None => {
                 // TBD, should we clear peer connection? This must respect stability.
-                if !links_json.topology_stable { clear_connection(local_interface, &connection); }
+                if !link_report.topology_stable && clear_connection(local_interface, connection)? { links_changed = true; }
                 continue;
             }
         }
 
         let peer_device : models::dbo::Device;
-        match models::dbo::Device::find_by_hostname_and_domain_name(&connection, &peer_interface_info.name, &peer_interface_info.dns_domain) {
+        match models::dbo::Device::find_by_hostname_and_domain_name(connection, &peer_interface_info.name, &peer_interface_info.dns_domain) {
             Some(some_peer_device) => {
                 peer_device = some_peer_device;
             },
             None => {
                 match local_interface.connected_interface {
                     Some(_) => {
-                        if !links_json.topology_stable { clear_connection(local_interface, &connection); }
+                        if !link_report.topology_stable && clear_connection(local_interface, connection)? { links_changed = true; }
                         continue;
                     },
                     None => {
@@ -199,7 +430,7 @@ pub fn discovery_links(
         }
 
         // todo if peer interface is same noop, if different then change, if no peer interface then change
-        match local_interface.peer_interface(&connection) {
+        match local_interface.peer_interface(connection) {
             Some(peer_interface) => {
                 let mut create_link = false;
                 let mut clear_other = false;
@@ -213,46 +444,44 @@ pub fn discovery_links(
                     clear_other = true;
                 }
                 if create_link {
-                    match peer_device.interface_by_name(&connection, &peer_interface_info.interface) {
+                    match peer_device.interface_by_name(connection, &peer_interface_info.interface) {
                         Some(new_peer_interface) => {
-                            // TBD: create link other way too? maybe not?
-                            let mut new_local_interface : models::dbo::Interface = local_interface.clone();
-                            new_local_interface.connected_interface = Some(new_peer_interface.id);
-                            match new_local_interface.update(&connection) {
-                                Ok(_) => {},
-                                Err(_) => {
-                                    // TODO: log?
-                                }
-                            }
+                            set_connection(local_interface, &new_peer_interface, &link_report.device_fqdn, connection)?;
+                            links_changed = true;
                         },
                         None => {
                             // other side interface not found, do some guesswork and/or clear any possible link?
                         }
                     }
                 }
-                if clear_other && !links_json.topology_stable {
+                if clear_other && !link_report.topology_stable {
+                    // The stale peer may sit on another device, which gets its own audit entry
+                    let stale_peer_device = models::dbo::Device::by_id(peer_interface.device_id, connection);
+                    let stale_peer_links_before = stale_peer_device.as_ref().map(|stale_peer_device| utilities::audit::links_state(stale_peer_device, connection));
                     let mut new_peer_interface : models::dbo::Interface = peer_interface.clone();
                     new_peer_interface.connected_interface = None;
-                    match new_peer_interface.update(&connection) {
-                        Ok(_) => {},
-                        Err(_) => {
-                            // TODO: log?
+                    if let Err(error) = new_peer_interface.update(connection) {
+                        log_error!("failed to clear stale peer link", "route" => "PUT /discovery/links", "fqdn" => link_report.device_fqdn, "interface" => peer_interface.name, "error" => error);
+                        return Err(utilities::errors::ApiError::from(error));
+                    }
+                    links_changed = true;
+                    if let Some(ref stale_peer_device) = stale_peer_device {
+                        if stale_peer_device.id != local_device.id {
+                            let stale_peer_fqdn = format!("{}.{}", stale_peer_device.name, stale_peer_device.dns_domain);
+                            let stale_peer_links_after = utilities::audit::links_state(stale_peer_device, connection);
+                            if let Some(ref stale_peer_links_before) = stale_peer_links_before {
+                                links_diff(&stale_peer_fqdn, stale_peer_links_before, &stale_peer_links_after, &mut links_diff_result);
+                            }
+                            utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE_LINKS, &stale_peer_fqdn, stale_peer_links_before, Some(stale_peer_links_after));
                         }
                     }
                 }
             },
             None => {
-                match peer_device.interface_by_name(&connection, &peer_interface_info.interface) {
+                match peer_device.interface_by_name(connection, &peer_interface_info.interface) {
                     Some(new_peer_interface) => {
-                        // TBD: create link other way too? maybe not?
-                        let mut new_local_interface : models::dbo::Interface = local_interface.clone();
-                        new_local_interface.connected_interface = Some(new_peer_interface.id);
-                        match new_local_interface.update(&connection) {
-                            Ok(_) => {},
-                            Err(_) => {
-                                // TODO: log?
-                            }
-                        }
+                        set_connection(local_interface, &new_peer_interface, &link_report.device_fqdn, connection)?;
+                        links_changed = true;
                     },
                     None => {
                         // other side interface not found, do some guesswork and/or clear any possible link?
@@ -262,12 +491,37 @@ pub fn discovery_links(
         }
     }
 
+    let links_after = utilities::audit::links_state(&local_device, connection);
+    links_diff(&link_report.device_fqdn, &links_before, &links_after, &mut links_diff_result);
+    utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE_LINKS, &link_report.device_fqdn, Some(links_before), Some(links_after));
+    return Ok((links_diff_result, links_changed));
+}
+
+// Same transaction and dryRun handling as discovery_device
+#[put("/links?<options..>", data = "<links_json>")]
+pub fn discovery_links(
+    auth: Authorized<DiscoveryScope>,
+    options: LenientForm<models::json::DryRunOptions>,
+    links_json: rocket_contrib::json::Json<models::json::LinkInfo>,
+    connection: db::Connection,
+    metric_miss_cache: State<Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>>,
+    cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>
+) -> Result<json::Json<models::json::DiscoveryLinksDiff>, utilities::errors::ApiError> {
+    let dry_run = options.dry_run.unwrap_or(false);
+    let (links_diff_result, links_changed) = db::transaction(&connection, dry_run, || ingest_links(&auth.token, &links_json, &connection, dry_run))?;
+    if dry_run {
+        return Ok(json::Json(links_diff_result));
+    }
+
     // TODO: optimize: only invalidate metric miss cache if stuff changes
-    let device_fqdn = format!("{}.{}", local_device.name, local_device.dns_domain);
+    let device_fqdn = links_diff_result.device_fqdn.clone();
     if let Ok(ref mut metric_miss_cache) = metric_miss_cache.inner().lock() {
         if !metric_miss_cache.miss_set.contains(&device_fqdn) { metric_miss_cache.miss_set.insert(device_fqdn); }
     }
 
     // Invalidate weathermap topology cache
-    if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_weathermap_cache(); }
+    if links_changed {
+        if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_topology_cache(); }
+    }
+    return Ok(json::Json(links_diff_result));
 }
@@ -0,0 +1,92 @@
+extern crate rocket_contrib;
+use models;
+use db;
+use rocket::get;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket_contrib::json;
+use std::sync::{Arc,Mutex};
+use std::collections::HashMap;
+use rocket::State;
+use utilities;
+
+fn health_check(ok: bool, detail: Option<String>) -> models::json::HealthCheck {
+    return models::json::HealthCheck {
+        ok: ok,
+        detail: detail,
+    };
+}
+
+fn health_response(checks: HashMap<String, models::json::HealthCheck>) -> status::Custom<json::Json<models::json::HealthReport>> {
+    let healthy = checks.values().all(|check| check.ok);
+    let health_report = models::json::HealthReport {
+        status: if healthy { "ok".to_string() } else { "unavailable".to_string() },
+        checks: checks,
+    };
+    if healthy {
+        return status::Custom(Status::Ok, json::Json(health_report));
+    } else {
+        return status::Custom(Status::ServiceUnavailable, json::Json(health_report));
+    }
+}
+
+// Answers as long as the HTTP server runs, dependencies are covered by /health/ready
+#[get("/live")]
+pub fn health_live() -> status::Custom<json::Json<models::json::HealthReport>> {
+    return health_response(HashMap::new());
+}
+
+#[get("/ready")]
+pub fn health_ready(config: State<utilities::config::Config>, pool: State<db::Pool>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, runtime_info: State<Arc<Mutex<models::internal::RuntimeInfo>>>) -> status::Custom<json::Json<models::json::HealthReport>> {
+    let worker_max_age : f64 = config.health.worker_max_age;
+    let mut checks : HashMap<String, models::json::HealthCheck> = HashMap::new();
+
+    // Taken from the pool directly, a db::Connection guard would fail the whole request instead
+    match pool.get() {
+        Ok(_) => {
+            checks.insert("database".to_string(), health_check(true, None));
+        },
+        Err(error) => {
+            checks.insert("database".to_string(), health_check(false, Some(format!("{}", error))));
+        }
+    }
+
+    match msgbus.lock() {
+        Ok(ref msgbus) => {
+            if msgbus.is_bound() {
+                checks.insert("messageBus".to_string(), health_check(true, None));
+            } else {
+                checks.insert("messageBus".to_string(), health_check(false, Some("socket not bound".to_string())));
+            }
+        },
+        Err(_) => {
+            checks.insert("messageBus".to_string(), health_check(false, Some("lock poisoned".to_string())));
+        }
+    }
+
+    match runtime_info.lock() {
+        Ok(ref runtime_info) => {
+            if runtime_info.imds_worker_first_run_done {
+                checks.insert("imdsWorkerFirstRun".to_string(), health_check(true, None));
+            } else {
+                checks.insert("imdsWorkerFirstRun".to_string(), health_check(false, Some("first refresh not finished".to_string())));
+            }
+            match runtime_info.imds_worker_last_iteration {
+                Some(last_iteration) => {
+                    let age = utilities::tools::get_time() - last_iteration;
+                    let detail = Some(format!("last iteration {:.1}s ago", age));
+                    checks.insert("imdsWorkerRecent".to_string(), health_check(age <= worker_max_age, detail));
+                },
+                None => {
+                    checks.insert("imdsWorkerRecent".to_string(), health_check(false, Some("no iterations yet".to_string())));
+                }
+            }
+        },
+        Err(_) => {
+            checks.insert("imdsWorkerFirstRun".to_string(), health_check(false, Some("lock poisoned".to_string())));
+            checks.insert("imdsWorkerRecent".to_string(), health_check(false, Some("lock poisoned".to_string())));
+        }
+    }
+
+    return health_response(checks);
+}
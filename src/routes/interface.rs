@@ -6,26 +6,53 @@ use rocket_contrib::json;
 use std::sync::{Arc,Mutex};
 use rocket::State;
 use utilities;
+use utilities::auth::{Authorized, ReadScope, ReportScope};
 
-#[get("/?<device_fqdn>")]
-pub fn interface_list(connection: db::Connection, device_fqdn: Option<String>) -> json::Json<Vec<models::dbo::Interface>> {
-    match device_fqdn {
+#[get("/?<device_fqdn>&<name>&<interface_type>&<has_neighbor>&<q>&<sort>&<order>&<limit>&<offset>")]
+pub fn interface_list(_auth: Authorized<ReadScope>, connection: db::Connection, device_fqdn: Option<String>, name: Option<String>, interface_type: Option<String>, has_neighbor: Option<bool>, q: Option<String>, sort: Option<String>, order: Option<String>, limit: Option<i64>, offset: Option<i64>) -> Result<utilities::pagination::Page<models::dbo::Interface>, utilities::errors::ApiError> {
+    let pagination = utilities::pagination::pagination(sort, order, limit, offset, &models::dbo::INTERFACE_SORT_FIELDS)?;
+    let device_id : Option<i32> = match device_fqdn {
         Some(device_fqdn) => {
-            if let Some(device) = models::dbo::Device::find_by_fqdn(&connection, &device_fqdn) {
-                let interfaces = device.interfaces(&connection);
-                return json::Json(interfaces);
-            };
-            return json::Json(Vec::new());
+            match models::dbo::Device::find_by_fqdn(&connection, &device_fqdn) {
+                Some(device) => Some(device.id),
+                None => { return Ok(utilities::pagination::Page { items: Vec::new(), total: 0 }); }
+            }
         },
-        None => {
-            return json::Json(models::dbo::Interface::all(&connection));
-        }
+        None => None,
+    };
+    let interface_filter = models::dbo::InterfaceFilter {
+        device_id: device_id,
+        name_prefix: name,
+        interface_type: interface_type,
+        has_neighbor: has_neighbor,
+        text: q,
     };
+    let (interfaces, total) = models::dbo::Interface::search(&interface_filter, &pagination, &connection)?;
+    return Ok(utilities::pagination::Page { items: interfaces, total: total });
 }
 
 #[put("/monitor", data = "<interface_monitor_report>")]
-pub fn interface_monitor_report(connection: db::Connection, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, interface_monitor_report : json::Json<models::json::InterfaceMonitorReport>) {
+pub fn interface_monitor_report(_auth: Authorized<ReportScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, interface_monitor_report : json::Json<models::json::InterfaceMonitorReport>) -> json::Json<models::json::MonitorReportResult> {
     if let Ok(ref mut imds) = imds.lock() {
-        imds.report_interfaces(&connection, interface_monitor_report.into_inner());
+        return json::Json(imds.report_interfaces(interface_monitor_report.into_inner()));
+    }
+    return json::Json(models::json::MonitorReportResult { fqdn: interface_monitor_report.device_fqdn.clone(), accepted: false, unknown_device: false, unknown_if_indexes: Vec::new() });
+}
+
+#[put("/monitor/batch", data = "<interface_monitor_reports>")]
+pub fn interface_monitor_report_batch(_auth: Authorized<ReportScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, interface_monitor_reports : json::Json<Vec<models::json::InterfaceMonitorReport>>) -> Result<json::Json<Vec<models::json::MonitorReportResult>>, utilities::errors::ApiError> {
+    let mut results : Vec<models::json::MonitorReportResult> = Vec::new();
+    // Rejecting every item would look like a normal resync request to the poller
+    match imds.lock() {
+        Ok(ref mut imds) => {
+            for interface_monitor_report in interface_monitor_reports.into_inner().into_iter() {
+                results.push(imds.report_interfaces(interface_monitor_report));
+            }
+        },
+        Err(_) => {
+            log_error!("IMDS lock poisoned, rejecting interface report batch", "count" => interface_monitor_reports.len());
+            return Err(utilities::errors::ApiError::Unavailable("in-memory metrics store is unavailable".to_string()));
+        }
     }
+    return Ok(json::Json(results));
 }
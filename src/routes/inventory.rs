@@ -0,0 +1,71 @@
+extern crate rocket_contrib;
+use models;
+use db;
+use rocket::{get, post};
+use rocket::Data;
+use rocket::request::LenientForm;
+use rocket::State;
+use rocket::response::content;
+use rocket_contrib::json;
+use std::io::Read;
+use std::sync::{Arc,Mutex};
+use utilities;
+use utilities::auth::{Authorized, AdminScope};
+
+const IMPORT_BODY_LIMIT: u64 = 16 * 1024 * 1024;
+
+#[get("/export?<format>")]
+pub fn inventory_export(_auth: Authorized<AdminScope>, connection: db::Connection, format: Option<String>) -> Result<content::Content<String>, utilities::errors::ApiError> {
+    let inventory_format = utilities::inventory::InventoryFormat::parse(&format).map_err(utilities::errors::ApiError::BadRequest)?;
+    let inventory = utilities::inventory::export(&connection);
+    let body = utilities::inventory::serialize(&inventory, inventory_format).map_err(utilities::errors::ApiError::Internal)?;
+    return Ok(content::Content(inventory_format.content_type(), body));
+}
+
+// Everything is applied in one transaction, a single bad entry rejects the whole file. With
+// dry_run the transaction is rolled back and only the computed changes are returned.
+#[post("/import?<format>&<options..>", data = "<data>")]
+pub fn inventory_import(auth: Authorized<AdminScope>, connection: db::Connection, format: Option<String>, options: LenientForm<models::json::DryRunOptions>, data: Data, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, metric_miss_cache: State<Arc<Mutex<models::metrics::DeviceMetricRefreshCacheMiss>>>, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>) -> Result<json::Json<models::json::InventoryImportResult>, utilities::errors::ApiError> {
+    let inventory_format = utilities::inventory::InventoryFormat::parse(&format).map_err(utilities::errors::ApiError::BadRequest)?;
+    let dry_run = options.dry_run.unwrap_or(false);
+    let mut body = String::new();
+    if let Err(error) = data.open().take(IMPORT_BODY_LIMIT + 1).read_to_string(&mut body) {
+        return Err(utilities::errors::ApiError::BadRequest(format!("failed to read inventory: {}", error)));
+    }
+    if body.len() as u64 > IMPORT_BODY_LIMIT {
+        return Err(utilities::errors::ApiError::PayloadTooLarge(format!("inventory is larger than {} bytes", IMPORT_BODY_LIMIT)));
+    }
+    let inventory = utilities::inventory::deserialize(&body, inventory_format).map_err(|error| utilities::errors::ApiError::BadRequest(format!("invalid inventory: {}", error)))?;
+    utilities::inventory::validate(&inventory).map_err(utilities::errors::ApiError::BadRequest)?;
+
+    let outcome = match db::transaction(&connection, dry_run, || utilities::inventory::apply(&inventory, &auth.token, &connection)) {
+        Ok(outcome) => outcome,
+        Err(error) => {
+            log_error!("inventory import failed", "route" => "POST /inventory/import", "by" => auth.token.name, "error" => error.message());
+            return Err(error);
+        }
+    };
+
+    if !dry_run {
+        log_info!("inventory imported", "route" => "POST /inventory/import", "devices" => inventory.devices.len(), "changes" => outcome.changes.len(), "by" => auth.token.name);
+        if let Ok(ref mut msgbus) = msgbus.lock() {
+            for event in outcome.events.into_iter() {
+                msgbus.event(event);
+            }
+        }
+        if !outcome.changed_devices.is_empty() {
+            if let Ok(ref mut metric_miss_cache) = metric_miss_cache.inner().lock() {
+                for device_fqdn in outcome.changed_devices.iter() {
+                    metric_miss_cache.miss_set.insert(device_fqdn.clone());
+                }
+            }
+            if let Ok(ref cache_controller) = cache_controller.lock() { cache_controller.invalidate_topology_cache(); }
+        }
+    }
+    return Ok(json::Json(models::json::InventoryImportResult {
+        dry_run: dry_run,
+        devices: inventory.devices.len(),
+        interfaces: inventory.devices.iter().map(|inventory_device| inventory_device.interfaces.len()).sum(),
+        changes: outcome.changes,
+    }));
+}
@@ -1,13 +1,16 @@
 extern crate rocket_contrib;
 use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
 use utilities;
 use rocket::State;
 use models;
+use db;
 use rocket::{get, put};
+use utilities::auth::{Authorized, ReadScope};
 
 
 #[get("/fast")]
-pub fn metrics_fast(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Option<String> {
+pub fn metrics_fast(_auth: Authorized<ReadScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Option<String> {
     let mut ret : String = String::new();
     let metrics : Option<Vec<models::metrics::LabeledMetric>>;
 
@@ -29,7 +32,7 @@ pub fn metrics_fast(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Option<St
 }
 
 #[get("/")]
-pub fn metrics(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Option<String> {
+pub fn metrics(_auth: Authorized<ReadScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Option<String> {
     let mut ret : String = String::new();
     let metrics : Option<Vec<models::metrics::LabeledMetric>>;
 
@@ -49,3 +52,43 @@ pub fn metrics(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> Option<String>
 
     return Some(ret);
 }
+
+#[get("/self")]
+pub fn metrics_self(_auth: Authorized<ReadScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, msgbus: State<Arc<Mutex<utilities::msgbus::MessageBus>>>, self_metrics: State<Arc<Mutex<utilities::selfmetrics::SelfMetrics>>>, pool: State<db::Pool>) -> Option<String> {
+    let mut ret : String = String::new();
+    let mut metrics : Vec<models::metrics::LabeledMetric> = Vec::new();
+
+    // Each lock is taken on its own, the worker holds IMDS while publishing to the message bus
+    if let Ok(ref mut imds) = imds.inner().lock() {
+        metrics.extend(imds.get_self_metrics());
+    }
+    if let Ok(ref mut msgbus) = msgbus.inner().lock() {
+        metrics.extend(msgbus.get_self_metrics());
+    }
+    if let Ok(ref mut self_metrics) = self_metrics.inner().lock() {
+        metrics.extend(self_metrics.get_metrics());
+    }
+
+    let pool_state = pool.state();
+    let no_labels: HashMap<String,String> = HashMap::new();
+    metrics.push(models::metrics::LabeledMetric::new(
+        &"jaspy_nexus_db_pool_connections".to_string(), models::metrics::MetricValue::Uint64(pool_state.connections as u64),
+        &no_labels
+    ));
+    metrics.push(models::metrics::LabeledMetric::new(
+        &"jaspy_nexus_db_pool_idle_connections".to_string(), models::metrics::MetricValue::Uint64(pool_state.idle_connections as u64),
+        &no_labels
+    ));
+    metrics.push(models::metrics::LabeledMetric::new(
+        &"jaspy_nexus_db_pool_max_connections".to_string(), models::metrics::MetricValue::Uint64(pool.max_size() as u64),
+        &no_labels
+    ));
+
+    for metric in metrics.iter() {
+        ret.push_str(&format!("{}\n", metric.as_text()))
+    }
+
+    ret.push_str("\n");
+
+    return Some(ret);
+}
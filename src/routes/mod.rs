@@ -4,3 +4,10 @@ pub mod device;
 pub mod metrics;
 pub mod interface;
 pub mod weathermap;
+pub mod topology;
+pub mod health;
+pub mod catchers;
+pub mod token;
+pub mod credentialprofile;
+pub mod audit;
+pub mod inventory;
\ No newline at end of file
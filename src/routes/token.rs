@@ -0,0 +1,91 @@
+extern crate rocket_contrib;
+use models;
+use db;
+use rocket::{get, post, delete};
+use rocket::State;
+use rocket_contrib::json;
+use std::sync::{Arc,Mutex};
+use utilities;
+use utilities::auth::{Authorized, AdminScope};
+
+fn token_info(api_token: &models::dbo::ApiToken) -> models::json::ApiTokenInfo {
+    return models::json::ApiTokenInfo {
+        id: api_token.id,
+        name: api_token.name.clone(),
+        scopes: api_token.scope_list(),
+        created_at: api_token.created_at,
+        revoked: api_token.revoked,
+    };
+}
+
+#[get("/")]
+pub fn token_list(_auth: Authorized<AdminScope>, connection: db::Connection) -> json::Json<Vec<models::json::ApiTokenInfo>> {
+    let mut token_infos : Vec<models::json::ApiTokenInfo> = Vec::new();
+    for api_token in models::dbo::ApiToken::all(&connection).iter() {
+        token_infos.push(token_info(api_token));
+    }
+    return json::Json(token_infos);
+}
+
+#[post("/", data = "<token_request>")]
+pub fn token_create(auth: Authorized<AdminScope>, connection: db::Connection, token_cache: State<Arc<Mutex<utilities::auth::TokenCache>>>, token_request: json::Json<models::json::NewApiTokenRequest>) -> Result<json::Json<models::json::CreatedApiToken>, utilities::errors::ApiError> {
+    let token_request = token_request.into_inner();
+    if token_request.name.trim().is_empty() {
+        return Err(utilities::errors::ApiError::BadRequest("token name must not be empty".to_string()));
+    }
+    if token_request.scopes.is_empty() {
+        return Err(utilities::errors::ApiError::BadRequest("at least one scope is required".to_string()));
+    }
+    for scope in token_request.scopes.iter() {
+        if !utilities::auth::SCOPES.contains(&scope.as_str()) {
+            return Err(utilities::errors::ApiError::BadRequest(format!("unknown scope {}, expected one of {}", scope, utilities::auth::SCOPES.join(", "))));
+        }
+    }
+
+    let token = utilities::auth::generate_token();
+    let new_api_token = models::dbo::NewApiToken {
+        name: token_request.name.trim().to_string(),
+        token_hash: utilities::auth::hash_token(&token),
+        scopes: token_request.scopes.join(","),
+        created_at: utilities::tools::get_time(),
+        revoked: false,
+    };
+    let api_token = models::dbo::ApiToken::create(&new_api_token, &connection)?;
+    if let Ok(ref mut token_cache) = token_cache.lock() { token_cache.invalidate(&api_token.token_hash); }
+    log_info!("API token created", "route" => "POST /token", "token" => api_token.name, "scopes" => api_token.scopes, "by" => auth.token.name);
+    // The token info never contains the hash
+    utilities::audit::record(&connection, &auth.token, utilities::audit::ENTITY_API_TOKEN, &api_token.name, None, Some(json!(token_info(&api_token))));
+    return Ok(json::Json(models::json::CreatedApiToken {
+        info: token_info(&api_token),
+        token: token,
+    }));
+}
+
+#[delete("/<id>")]
+pub fn token_revoke(auth: Authorized<AdminScope>, connection: db::Connection, token_cache: State<Arc<Mutex<utilities::auth::TokenCache>>>, id: i32) -> Result<json::Json<models::json::ApiTokenInfo>, utilities::errors::ApiError> {
+    let mut api_token = match models::dbo::ApiToken::by_id(id, &connection) {
+        Some(api_token) => api_token,
+        None => { return Err(utilities::errors::ApiError::NotFound(format!("token {} not found", id))); }
+    };
+    if !api_token.revoked {
+        let api_token_before = json!(token_info(&api_token));
+        api_token.revoked = true;
+        api_token.update(&connection)?;
+        if let Ok(ref mut token_cache) = token_cache.lock() { token_cache.invalidate(&api_token.token_hash); }
+        log_info!("API token revoked", "route" => "DELETE /token", "token" => api_token.name, "by" => auth.token.name);
+        utilities::audit::record(&connection, &auth.token, utilities::audit::ENTITY_API_TOKEN, &api_token.name, Some(api_token_before), Some(json!(token_info(&api_token))));
+    }
+    return Ok(json::Json(token_info(&api_token)));
+}
+
+#[get("/<id>/usage")]
+pub fn token_usage(_auth: Authorized<AdminScope>, connection: db::Connection, id: i32) -> Result<json::Json<Vec<models::dbo::ApiTokenUsage>>, utilities::errors::ApiError> {
+    match models::dbo::ApiToken::by_id(id, &connection) {
+        Some(api_token) => {
+            return Ok(json::Json(api_token.usages(&connection)));
+        },
+        None => {
+            return Err(utilities::errors::ApiError::NotFound(format!("token {} not found", id)));
+        }
+    }
+}
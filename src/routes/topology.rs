@@ -0,0 +1,313 @@
+extern crate rocket_contrib;
+use models;
+use db;
+use rocket::{get, post};
+use rocket_contrib::json;
+use std::net::IpAddr;
+use std::sync::{Arc,Mutex};
+use rocket::State;
+use utilities;
+use utilities::auth::{Authorized, AdminScope, ReadScope};
+
+fn topology_graph(connection: &db::Connection, cache_controller: &Arc<Mutex<utilities::cache::CacheController>>) -> Arc<utilities::topology::TopologyGraph> {
+    match cache_controller.lock() {
+        Ok(ref cache_controller) => {
+            return cache_controller.topology_graph(connection);
+        },
+        Err(_) => {
+            log_error!("cache controller lock poisoned, loading topology uncached");
+            return Arc::new(utilities::topology::TopologyGraph::load(connection));
+        }
+    }
+}
+
+// Endpoints are either device fqdns or client IPs known through client_locations
+fn resolve_endpoint(connection: &db::Connection, graph: &utilities::topology::TopologyGraph, endpoint: &String) -> Result<(i32, Option<models::dbo::ClientLocation>), utilities::errors::ApiError> {
+    if endpoint.parse::<IpAddr>().is_ok() {
+        match models::dbo::ClientLocation::by_ip(endpoint, connection) {
+            Some(client_location) => {
+                return Ok((client_location.device_id, Some(client_location)));
+            },
+            None => {
+                return Err(utilities::errors::ApiError::NotFound(format!("no known location for client {}", endpoint)));
+            }
+        }
+    }
+    match graph.device_id_by_fqdn(endpoint) {
+        Some(device_id) => {
+            return Ok((device_id, None));
+        },
+        None => {
+            return Err(utilities::errors::ApiError::NotFound(format!("device {} not found", endpoint)));
+        }
+    }
+}
+
+// The graph is loaded as a whole, a device id without fqdn means it is inconsistent
+fn device_fqdn(graph: &utilities::topology::TopologyGraph, device_id: i32) -> Result<String, utilities::errors::ApiError> {
+    return graph.fqdn(device_id).ok_or_else(|| utilities::errors::ApiError::Internal(format!("device {} is missing from the topology graph", device_id)));
+}
+
+fn stored_snapshot_data(id: i32, connection: &db::Connection) -> Result<models::json::TopologySnapshotData, utilities::errors::ApiError> {
+    let topology_snapshot = match models::dbo::TopologySnapshot::by_id(id, connection) {
+        Some(topology_snapshot) => topology_snapshot,
+        None => { return Err(utilities::errors::ApiError::NotFound(format!("topology snapshot {} not found", id))); }
+    };
+    return utilities::topology::snapshot_data(&topology_snapshot).ok_or_else(|| utilities::errors::ApiError::Internal(format!("topology snapshot {} is corrupt", id)));
+}
+
+fn link_info(graph: &utilities::topology::TopologyGraph, link: &utilities::topology::TopologyLink) -> Option<models::json::TopologyLinkInfo> {
+    return Some(models::json::TopologyLinkInfo {
+        fqdn: graph.fqdn(link.local_device)?,
+        interface: graph.interfaces.get(&link.local_interface)?.name(),
+        peer_fqdn: graph.fqdn(link.remote_device)?,
+        peer_interface: graph.interfaces.get(&link.remote_interface)?.name(),
+    });
+}
+
+// Reachability only means something relative to the roots, so an analysis without any is refused
+fn resolve_roots(graph: &utilities::topology::TopologyGraph, topology_roots: &utilities::topology::TopologyRoots) -> Result<Vec<i32>, utilities::errors::ApiError> {
+    if topology_roots.fqdns.is_empty() {
+        return Err(utilities::errors::ApiError::Unavailable("no topology roots are configured, set topology.roots or TOPOLOGY_ROOTS".to_string()));
+    }
+    let mut roots : Vec<i32> = Vec::new();
+    for root_fqdn in topology_roots.fqdns.iter() {
+        if let Some(device_id) = graph.device_id_by_fqdn(root_fqdn) {
+            roots.push(device_id);
+        }
+    }
+    if roots.is_empty() {
+        return Err(utilities::errors::ApiError::Unavailable(format!("none of the configured topology roots ({}) is a known device", topology_roots.fqdns.join(", "))));
+    }
+    return Ok(roots);
+}
+
+fn path_interface(graph: &utilities::topology::TopologyGraph, imds: &utilities::imds::IMDS, interface_id: i32) -> Result<models::json::TopologyPathInterface, utilities::errors::ApiError> {
+    let interface = match graph.interfaces.get(&interface_id) {
+        Some(interface) => interface,
+        None => { return Err(utilities::errors::ApiError::Internal(format!("interface {} is missing from the topology graph", interface_id))); }
+    };
+    let fqdn = device_fqdn(graph, interface.device_id)?;
+    let mut path_interface = models::json::TopologyPathInterface {
+        fqdn: fqdn,
+        interface: interface.name(),
+        if_index: interface.index,
+        up: None,
+        speed: interface.speed_override,
+        rx_bps: None,
+        tx_bps: None,
+    };
+    if let Some(interface_metrics) = imds.get_interface(&path_interface.fqdn, interface.index) {
+        path_interface.up = interface_metrics.up;
+        if path_interface.speed.is_none() { path_interface.speed = interface_metrics.speed; }
+        path_interface.rx_bps = interface_metrics.in_bps;
+        path_interface.tx_bps = interface_metrics.out_bps;
+    }
+    return Ok(path_interface);
+}
+
+fn link_utilization(from: &models::json::TopologyPathInterface) -> Option<f64> {
+    // Interface speeds are reported in Mbit/s
+    let speed = match from.speed {
+        Some(speed) if speed > 0 => (speed as f64) * 1e6,
+        _ => { return None; }
+    };
+    let mut busiest : Option<f64> = None;
+    for bps in [from.rx_bps, from.tx_bps].iter() {
+        if let Some(bps) = *bps {
+            if busiest.map_or(true, |busiest| bps > busiest) { busiest = Some(bps); }
+        }
+    }
+    return busiest.map(|bps| bps / speed);
+}
+
+#[get("/path?<from>&<to>")]
+pub fn topology_path(_auth: Authorized<ReadScope>, connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>, from: String, to: String) -> Result<json::Json<models::json::TopologyPath>, utilities::errors::ApiError> {
+    let graph = topology_graph(&connection, &cache_controller);
+    let (from_device, client_location) = resolve_endpoint(&connection, &graph, &from)?;
+    let (to_device, _) = resolve_endpoint(&connection, &graph, &to)?;
+    let links = match graph.shortest_path(from_device, to_device) {
+        Some(links) => links,
+        None => { return Err(utilities::errors::ApiError::NotFound(format!("no path between {} and {}", from, to))); }
+    };
+
+    let mut topology_path = models::json::TopologyPath {
+        client: None,
+        devices: Vec::new(),
+        hops: Vec::new(),
+    };
+    if let Some(client_location) = client_location {
+        topology_path.client = Some(models::json::TopologyPathClient {
+            ip_address: client_location.ip_address,
+            fqdn: device_fqdn(&graph, client_location.device_id)?,
+            port_info: client_location.port_info,
+        });
+    }
+    topology_path.devices.push(device_fqdn(&graph, from_device)?);
+
+    if let Ok(ref imds) = imds.lock() {
+        for link in links.iter() {
+            let hop_from = path_interface(&graph, imds, link.local_interface)?;
+            let hop_to = path_interface(&graph, imds, link.remote_interface)?;
+            topology_path.devices.push(hop_to.fqdn.clone());
+            topology_path.hops.push(models::json::TopologyPathHop {
+                utilization: link_utilization(&hop_from),
+                from: hop_from,
+                to: hop_to,
+            });
+        }
+    } else {
+        return Err(utilities::errors::ApiError::Unavailable("interface metrics are unavailable".to_string()));
+    }
+
+    return Ok(json::Json(topology_path));
+}
+
+#[get("/spof")]
+pub fn topology_spof(_auth: Authorized<ReadScope>, connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>) -> json::Json<models::json::TopologySpofReport> {
+    let graph = topology_graph(&connection, &cache_controller);
+    let cut_analysis = graph.cut_analysis();
+    let mut spof_report = models::json::TopologySpofReport {
+        articulation_points: Vec::new(),
+        bridges: Vec::new(),
+    };
+    for device_id in cut_analysis.articulation_points.iter() {
+        if let Some(fqdn) = graph.fqdn(*device_id) {
+            spof_report.articulation_points.push(fqdn);
+        }
+    }
+    for bridge in cut_analysis.bridges.iter() {
+        if let Some(bridge_info) = link_info(&graph, bridge) {
+            spof_report.bridges.push(bridge_info);
+        }
+    }
+    return json::Json(spof_report);
+}
+
+// Removes either a whole device, or the link on the given interface of that device
+#[get("/impact?<device>&<interface>")]
+pub fn topology_impact(_auth: Authorized<ReadScope>, connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, topology_roots: State<utilities::topology::TopologyRoots>, device: String, interface: Option<String>) -> Result<json::Json<models::json::TopologyImpactReport>, utilities::errors::ApiError> {
+    let graph = topology_graph(&connection, &cache_controller);
+    let roots = resolve_roots(&graph, &topology_roots)?;
+    let device_id = match graph.device_id_by_fqdn(&device) {
+        Some(device_id) => device_id,
+        None => { return Err(utilities::errors::ApiError::NotFound(format!("device {} not found", device))); }
+    };
+
+    let mut impact_report = models::json::TopologyImpactReport {
+        roots: Vec::new(),
+        removed_device: None,
+        removed_link: None,
+        unreachable: Vec::new(),
+    };
+    for root in roots.iter() {
+        impact_report.roots.push(device_fqdn(&graph, *root)?);
+    }
+
+    let removed_device : Option<i32>;
+    let removed_interface : Option<i32>;
+    match interface {
+        Some(interface_name) => {
+            let link = match graph.device_links(device_id).iter()
+                .find(|link| graph.interfaces.get(&link.local_interface).map_or(false, |local_interface| local_interface.name == interface_name || local_interface.name() == interface_name))
+            {
+                Some(link) => link,
+                None => { return Err(utilities::errors::ApiError::NotFound(format!("device {} has no link on interface {}", device, interface_name))); }
+            };
+            impact_report.removed_link = link_info(&graph, link);
+            removed_device = None;
+            removed_interface = Some(link.local_interface);
+        },
+        None => {
+            impact_report.removed_device = Some(device.clone());
+            removed_device = Some(device_id);
+            removed_interface = None;
+        }
+    }
+
+    let reachable_before = graph.distances_from(&roots, None, None);
+    let reachable_after = graph.distances_from(&roots, removed_device, removed_interface);
+    for reachable_device in reachable_before.keys() {
+        if Some(*reachable_device) == removed_device { continue; }
+        if reachable_after.contains_key(reachable_device) { continue; }
+        if let Some(fqdn) = graph.fqdn(*reachable_device) {
+            impact_report.unreachable.push(fqdn);
+        }
+    }
+    impact_report.unreachable.sort();
+
+    return Ok(json::Json(impact_report));
+}
+
+#[get("/single-uplink")]
+pub fn topology_single_uplink(_auth: Authorized<ReadScope>, connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, topology_roots: State<utilities::topology::TopologyRoots>) -> Result<json::Json<Vec<models::json::TopologySingleUplink>>, utilities::errors::ApiError> {
+    let graph = topology_graph(&connection, &cache_controller);
+    let roots = resolve_roots(&graph, &topology_roots)?;
+    let mut single_uplinks : Vec<models::json::TopologySingleUplink> = Vec::new();
+    for uplink in graph.single_uplink_devices(&roots).iter() {
+        if let Some(uplink_info) = link_info(&graph, uplink) {
+            single_uplinks.push(models::json::TopologySingleUplink {
+                fqdn: uplink_info.fqdn.clone(),
+                uplink: uplink_info,
+            });
+        }
+    }
+    return Ok(json::Json(single_uplinks));
+}
+
+fn snapshot_info(topology_snapshot: &models::dbo::TopologySnapshot) -> models::json::TopologySnapshotInfo {
+    return models::json::TopologySnapshotInfo {
+        id: topology_snapshot.id,
+        created_at: topology_snapshot.created_at,
+        reason: topology_snapshot.reason.clone(),
+    };
+}
+
+#[get("/snapshot")]
+pub fn topology_snapshot_list(_auth: Authorized<ReadScope>, connection: db::Connection) -> json::Json<Vec<models::json::TopologySnapshotInfo>> {
+    let mut snapshot_infos : Vec<models::json::TopologySnapshotInfo> = Vec::new();
+    for topology_snapshot_summary in models::dbo::TopologySnapshot::summaries(&connection).into_iter() {
+        snapshot_infos.push(models::json::TopologySnapshotInfo {
+            id: topology_snapshot_summary.id,
+            created_at: topology_snapshot_summary.created_at,
+            reason: topology_snapshot_summary.reason,
+        });
+    }
+    return json::Json(snapshot_infos);
+}
+
+#[post("/snapshot")]
+pub fn topology_snapshot_create(_auth: Authorized<AdminScope>, connection: db::Connection) -> Result<json::Json<models::json::TopologySnapshotInfo>, utilities::errors::ApiError> {
+    match utilities::topology::take_snapshot(&connection, "manual", false) {
+        Ok(Some(topology_snapshot)) => {
+            log_info!("topology snapshot taken", "route" => "POST /topology/snapshot", "snapshot_id" => topology_snapshot.id);
+            return Ok(json::Json(snapshot_info(&topology_snapshot)));
+        },
+        Ok(None) => {
+            // Only periodic snapshots skip unchanged topologies
+            return Err(utilities::errors::ApiError::Internal("topology snapshot was not stored".to_string()));
+        },
+        Err(error) => {
+            log_error!("failed to store topology snapshot", "route" => "POST /topology/snapshot", "error" => error);
+            return Err(utilities::errors::ApiError::from(error));
+        }
+    }
+}
+
+#[get("/snapshot/<id>")]
+pub fn topology_snapshot_data(_auth: Authorized<ReadScope>, connection: db::Connection, id: i32) -> Result<json::Json<models::json::TopologySnapshotData>, utilities::errors::ApiError> {
+    return stored_snapshot_data(id, &connection).map(json::Json);
+}
+
+// Without <to> the snapshot is compared against the current topology
+#[get("/diff?<from>&<to>")]
+pub fn topology_snapshot_diff(_auth: Authorized<ReadScope>, connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, from: i32, to: Option<i32>) -> Result<json::Json<models::json::TopologySnapshotDiff>, utilities::errors::ApiError> {
+    let from_data = stored_snapshot_data(from, &connection)?;
+    let to_data = match to {
+        Some(to) => stored_snapshot_data(to, &connection)?,
+        None => {
+            topology_graph(&connection, &cache_controller).snapshot_data()
+        }
+    };
+    return Ok(json::Json(utilities::topology::snapshot_diff(from, &from_data, to, &to_data)));
+}
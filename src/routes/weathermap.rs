@@ -2,35 +2,40 @@ extern crate rocket_contrib;
 use rocket::{get, put};
 use models;
 use db;
+use diesel;
 use rocket_contrib::json;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use utilities;
 use rocket::State;
 use std::ops::DerefMut;
+use utilities::auth::{Authorized, ReadScope, WeathermapEditScope};
 
-fn get_topology_data(connection: &db::Connection) -> models::json::WeathermapBase {
+fn get_topology_data(topology_graph: &utilities::topology::TopologyGraph) -> models::json::WeathermapBase {
     let mut wmap: models::json::WeathermapBase = models::json::WeathermapBase {
         devices: HashMap::new(),
     };
-    let devices = models::dbo::Device::all(&connection);
-    for device in devices.iter() {
+    for device in topology_graph.devices.values() {
         let device_fqdn = format!("{}.{}", device.name, device.dns_domain);
         let mut weathermap_device = models::json::WeathermapDevice {
             fqdn: device_fqdn.clone(),
             interfaces: HashMap::new(),
         };
 
-        for interface in device.interfaces(&connection) {
-            let connected_interface: Option<models::json::WeathermapDeviceInterfaceConnectedTo> = match interface.peer_interface(&connection) {
+        for interface in topology_graph.device_interfaces(device.id) {
+            let connected_interface: Option<models::json::WeathermapDeviceInterfaceConnectedTo> = match topology_graph.peer_interface(interface.id) {
                 Some(peer_interface) => {
-                    let peer_device = peer_interface.device(&connection);
-                    let peer_device_fqdn = format!("{}.{}", peer_device.name, peer_device.dns_domain);
-                    
-                    Some(models::json::WeathermapDeviceInterfaceConnectedTo {
-                        fqdn: peer_device_fqdn,
-                        interface: peer_interface.name(),
-                    })
+                    match topology_graph.fqdn(peer_interface.device_id) {
+                        Some(peer_device_fqdn) => {
+                            Some(models::json::WeathermapDeviceInterfaceConnectedTo {
+                                fqdn: peer_device_fqdn,
+                                interface: peer_interface.name(),
+                            })
+                        },
+                        None => {
+                            None
+                        }
+                    }
                 },
                 None => {
                     None
@@ -54,44 +59,75 @@ fn get_topology_data(connection: &db::Connection) -> models::json::WeathermapBas
 }
 
 #[get("/")]
-pub fn full_topology_data(connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>) -> json::Json<models::json::WeathermapBase> {
+pub fn full_topology_data(_auth: Authorized<ReadScope>, connection: db::Connection, cache_controller: State<Arc<Mutex<utilities::cache::CacheController>>>, runtime_info: State<Arc<Mutex<models::internal::RuntimeInfo>>>, self_metrics: State<Arc<Mutex<utilities::selfmetrics::SelfMetrics>>>, if_none_match: utilities::cache::IfNoneMatch) -> utilities::cache::CachedJsonResponse {
     let cached_weathermap_topology_arc: Arc<Mutex<Option<utilities::cache::CachedWeathermapTopology>>>;
+    let cached_topology_graph_arc: Arc<Mutex<Option<utilities::cache::CachedTopologyGraph>>>;
+    let weathermap_cache_ttl: f64;
     if let Ok(cache_controller) = cache_controller.inner().lock() {
         cached_weathermap_topology_arc = cache_controller.cached_weathermap_topology.clone();
+        cached_topology_graph_arc = cache_controller.cached_topology_graph.clone();
+        weathermap_cache_ttl = cache_controller.weathermap_cache_ttl;
     } else {
-        // TODO: log, this means cache is somehow VERY broken
+        log_error!("cache controller lock poisoned, serving weathermap without cache", "route" => "GET /weathermap");
         cached_weathermap_topology_arc = Arc::new(Mutex::new(None));
+        cached_topology_graph_arc = Arc::new(Mutex::new(None));
+        weathermap_cache_ttl = 0.0;
     }
 
-    let mut ret : models::json::WeathermapBase = models::json::WeathermapBase {
-        devices: HashMap::new()
-    };
+    // Versions restart from scratch on every startup, the state id keeps ETags unique across restarts
+    let state_id : i64;
+    if let Ok(ref rti) = runtime_info.lock() {
+        state_id = rti.state_id();
+    } else {
+        state_id = 0;
+    }
+
+    let mut version : u64 = 0;
+    let mut ret : String = format!("{}", json!(models::json::WeathermapBase { devices: HashMap::new() }));
 
     if let Ok(ref mut cached_weathermap_topology_option_mutex) = cached_weathermap_topology_arc.lock() {
         let mut cached_weathermap_topology_option: &mut Option<utilities::cache::CachedWeathermapTopology> = cached_weathermap_topology_option_mutex.deref_mut();
-        let cache_refresh: bool;
-        if let Some(cached_weathermap_topology_data) = cached_weathermap_topology_option {
+        let mut cache_refresh: bool = true;
+        if let Some(ref cached_weathermap_topology_data) = *cached_weathermap_topology_option {
             let current_time = utilities::tools::get_time();
             if current_time < cached_weathermap_topology_data.valid_until {
-                ret = cached_weathermap_topology_data.weathermap_topology.clone();
                 cache_refresh = false;
-            } else {
-                ret = get_topology_data(&connection);
-                cache_refresh = true;
             }
-        } else {
-            ret = get_topology_data(&connection);
-            cache_refresh = true;
+        }
+        if let Ok(ref mut self_metrics) = self_metrics.lock() {
+            self_metrics.record_weathermap_cache(!cache_refresh);
         }
         if cache_refresh {
-            *cached_weathermap_topology_option = Some(utilities::cache::CachedWeathermapTopology::new(ret.clone()));
+            // serde_json values keep object keys sorted, so equal topologies serialize identically
+            let topology_graph = utilities::cache::load_topology_graph(&cached_topology_graph_arc, weathermap_cache_ttl, &connection);
+            let serialized_topology = format!("{}", json!(get_topology_data(&topology_graph)));
+            let new_version = match *cached_weathermap_topology_option {
+                Some(ref cached_weathermap_topology_data) => {
+                    if cached_weathermap_topology_data.serialized_topology == serialized_topology {
+                        cached_weathermap_topology_data.version
+                    } else {
+                        cached_weathermap_topology_data.version + 1
+                    }
+                },
+                None => 1,
+            };
+            *cached_weathermap_topology_option = Some(utilities::cache::CachedWeathermapTopology::new(new_version, serialized_topology, weathermap_cache_ttl));
+        }
+        if let Some(ref cached_weathermap_topology_data) = *cached_weathermap_topology_option {
+            version = cached_weathermap_topology_data.version;
+            ret = cached_weathermap_topology_data.serialized_topology.clone();
         }
     }
-    return json::Json(ret);
+
+    let etag = format!("\"{}-{}\"", state_id, version);
+    if if_none_match.matches(&etag) {
+        return utilities::cache::CachedJsonResponse::NotModified(etag);
+    }
+    return utilities::cache::CachedJsonResponse::Modified(etag, ret);
 }
 
 #[get("/state")]
-pub fn state_information(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> json::Json<models::json::WeathermapStateBase> {
+pub fn state_information(_auth: Authorized<ReadScope>, imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> json::Json<models::json::WeathermapStateBase> {
     let metrics : Option<Vec<models::metrics::LabeledMetric>>;
 
     if let Ok(ref mut imds) = imds.inner().lock() {
@@ -131,6 +167,13 @@ pub fn state_information(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> json
                                 } else {
                                     device.state = false;
                                 }
+                            },
+                            models::metrics::MetricValue::Float64(v) => {
+                                if v == 1.0 {
+                                    device.state = true;
+                                } else {
+                                    device.state = false;
+                                }
                             }
                         }
                     } else if metric.name == "jaspy_interface_up" {
@@ -139,7 +182,7 @@ pub fn state_information(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> json
                         }
                         if let Some(interface_name) = metric_labels.get("name") {
                             if device.interfaces.contains_key(interface_name) {
-                                // TODO: log, this should never happen!
+                                log_warn!("duplicate interface name in metrics", "route" => "GET /weathermap/state", "fqdn" => fqdn, "interface" => interface_name);
                                 continue;
                             }
                             let state;
@@ -157,6 +200,13 @@ pub fn state_information(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> json
                                     } else {
                                         state = false;
                                     }
+                                },
+                                models::metrics::MetricValue::Float64(v) => {
+                                    if v == 1.0 {
+                                        state = true;
+                                    } else {
+                                        state = false;
+                                    }
                                 }
                             }
                             device.interfaces.insert(interface_name.clone(), models::json::WeathermapStateDeviceInterfaceState {
@@ -174,7 +224,7 @@ pub fn state_information(imds: State<Arc<Mutex<utilities::imds::IMDS>>>) -> json
 
 
 #[get("/position")]
-pub fn get_position_data(connection: db::Connection) -> json::Json<models::json::WeathermapPositionInfoBase> {
+pub fn get_position_data(_auth: Authorized<ReadScope>, connection: db::Connection) -> json::Json<models::json::WeathermapPositionInfoBase> {
     let mut weathermap_position_info = models::json::WeathermapPositionInfoBase {
         devices: HashMap::new(),
     };
@@ -197,9 +247,12 @@ pub fn get_position_data(connection: db::Connection) -> json::Json<models::json:
 }
 
 #[put("/position", data = "<device_position_info>")]
-pub fn put_position_data(connection: db::Connection, device_position_info : json::Json<models::json::WeathermapPositionInfoUpdateDeviceInfo>) {
+pub fn put_position_data(auth: Authorized<WeathermapEditScope>, connection: db::Connection, device_position_info : json::Json<models::json::WeathermapPositionInfoUpdateDeviceInfo>) -> Result<(), utilities::errors::ApiError> {
     let new_position_info = device_position_info.into_inner();
-    if let Ok(_updated_item) = models::dbo::WeathermapDeviceInfo::update_by_fqdn_or_create(
+    let position_before = models::dbo::Device::find_by_fqdn(&connection, &new_position_info.device_fqdn)
+        .and_then(|device| device.weathermap_info(&connection))
+        .map(|weathermap_info| json!(weathermap_info));
+    match models::dbo::WeathermapDeviceInfo::update_by_fqdn_or_create(
         &connection,
         &new_position_info.device_fqdn,
         models::dbo::UpdatedWeathermapDeviceInfo { 
@@ -209,6 +262,15 @@ pub fn put_position_data(connection: db::Connection, device_position_info : json
             super_node: new_position_info.super_node,
         }
     ) {
-
+        Ok(updated_item) => {
+            utilities::audit::record(&connection, &auth.token, utilities::audit::ENTITY_WEATHERMAP_POSITION, &new_position_info.device_fqdn, position_before, Some(json!(updated_item)));
+            return Ok(());
+        },
+        Err(diesel::result::Error::NotFound) => {
+            return Err(utilities::errors::ApiError::NotFound(format!("device {} not found", new_position_info.device_fqdn)));
+        },
+        Err(error) => {
+            return Err(utilities::errors::ApiError::from(error));
+        }
     }
 }
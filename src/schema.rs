@@ -1,3 +1,40 @@
+table! {
+    api_token_usages (id) {
+        id -> Int4,
+        token_id -> Nullable<Int4>,
+        token_name -> Varchar,
+        used_at -> Float8,
+        method -> Varchar,
+        uri -> Varchar,
+        status -> Int4,
+    }
+}
+
+table! {
+    api_tokens (id) {
+        id -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Varchar,
+        created_at -> Float8,
+        revoked -> Bool,
+    }
+}
+
+table! {
+    audit_entries (id) {
+        id -> Int4,
+        created_at -> Float8,
+        token_id -> Nullable<Int4>,
+        token_name -> Varchar,
+        entity_type -> Varchar,
+        entity_key -> Varchar,
+        action -> Varchar,
+        before_data -> Nullable<Text>,
+        after_data -> Nullable<Text>,
+    }
+}
+
 table! {
     client_locations (id) {
         id -> Int4,
@@ -7,15 +44,37 @@ table! {
     }
 }
 
+table! {
+    credential_profiles (id) {
+        id -> Int4,
+        name -> Varchar,
+        snmp_version -> Varchar,
+        community -> Nullable<Varchar>,
+        username -> Nullable<Varchar>,
+        auth_protocol -> Nullable<Varchar>,
+        auth_password -> Nullable<Varchar>,
+        priv_protocol -> Nullable<Varchar>,
+        priv_password -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    device_credentials (id) {
+        id -> Int4,
+        device_id -> Int4,
+        snmp_community -> Nullable<Varchar>,
+    }
+}
+
 table! {
     devices (id) {
         id -> Int4,
         name -> Varchar,
         dns_domain -> Varchar,
-        snmp_community -> Nullable<Varchar>,
         base_mac -> Nullable<Varchar>,
         polling_enabled -> Nullable<Bool>,
         os_info -> Nullable<Varchar>,
+        credential_profile_id -> Nullable<Int4>,
     }
 }
 
@@ -33,6 +92,16 @@ table! {
         polling_enabled -> Nullable<Bool>,
         speed_override -> Nullable<Int4>,
         virtual_connection -> Nullable<Int4>,
+        aggregate_interface -> Nullable<Int4>,
+    }
+}
+
+table! {
+    topology_snapshots (id) {
+        id -> Int4,
+        created_at -> Float8,
+        reason -> Varchar,
+        data -> Text,
     }
 }
 
@@ -47,13 +116,23 @@ table! {
     }
 }
 
+joinable!(api_token_usages -> api_tokens (token_id));
+joinable!(audit_entries -> api_tokens (token_id));
 joinable!(client_locations -> devices (device_id));
+joinable!(device_credentials -> devices (device_id));
+joinable!(devices -> credential_profiles (credential_profile_id));
 joinable!(interfaces -> devices (device_id));
 joinable!(weathermap_device_infos -> devices (device_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_token_usages,
+    api_tokens,
+    audit_entries,
     client_locations,
+    credential_profiles,
+    device_credentials,
     devices,
     interfaces,
+    topology_snapshots,
     weathermap_device_infos,
 );
@@ -0,0 +1,107 @@
+use models;
+use serde_json;
+use std::collections::BTreeMap;
+use diesel::pg::PgConnection;
+use utilities::auth::AuthenticatedToken;
+use utilities::tools;
+
+pub const ENTITY_DEVICE: &str = "device";
+pub const ENTITY_DEVICE_INTERFACES: &str = "deviceInterfaces";
+pub const ENTITY_DEVICE_LINKS: &str = "deviceLinks";
+pub const ENTITY_WEATHERMAP_POSITION: &str = "weathermapPosition";
+pub const ENTITY_API_TOKEN: &str = "apiToken";
+pub const ENTITY_CREDENTIAL_PROFILE: &str = "credentialProfile";
+
+pub const ACTION_CREATE: &str = "create";
+pub const ACTION_UPDATE: &str = "update";
+pub const ACTION_DELETE: &str = "delete";
+
+// Keyed by interface name so that a diff of two states reads naturally
+pub fn interfaces_state(interfaces: &Vec<models::dbo::Interface>) -> serde_json::Value {
+    let mut state = serde_json::Map::new();
+    for interface in interfaces.iter() {
+        state.insert(interface.name.clone(), json!(interface));
+    }
+    return serde_json::Value::Object(state);
+}
+
+// Peers are recorded by name, interface ids mean nothing to whoever reads the log later
+pub fn links_state(device: &models::dbo::Device, connection: &PgConnection) -> serde_json::Value {
+    let mut state = serde_json::Map::new();
+    for interface in device.interfaces(connection).iter() {
+        let peer = match interface.peer_interface(connection) {
+            Some(peer_interface) => {
+                match models::dbo::Device::by_id(peer_interface.device_id, connection) {
+                    Some(peer_device) => json!(format!("{}.{} {}", peer_device.name, peer_device.dns_domain, peer_interface.name)),
+                    None => serde_json::Value::Null,
+                }
+            },
+            None => serde_json::Value::Null,
+        };
+        state.insert(interface.name.clone(), peer);
+    }
+    return serde_json::Value::Object(state);
+}
+
+// Field level differences between two serialized objects, ids are internal and left out
+pub fn field_changes(before: &serde_json::Value, after: &serde_json::Value) -> BTreeMap<String, models::json::FieldChange> {
+    let mut fields : BTreeMap<String, models::json::FieldChange> = BTreeMap::new();
+    let empty = serde_json::Map::new();
+    let before_fields = before.as_object().unwrap_or(&empty);
+    let after_fields = after.as_object().unwrap_or(&empty);
+    for (field, after_value) in after_fields.iter() {
+        if field == "id" || field == "deviceId" { continue; }
+        let before_value = before_fields.get(field).cloned().unwrap_or(serde_json::Value::Null);
+        if before_value != *after_value {
+            fields.insert(field.clone(), models::json::FieldChange { before: before_value, after: after_value.clone() });
+        }
+    }
+    return fields;
+}
+
+// Nothing is recorded when both states are equal. A failure to record is logged but must not fail
+// the change itself, which has already been made.
+pub fn record(connection: &PgConnection, token: &AuthenticatedToken, entity_type: &str, entity_key: &str, before: Option<serde_json::Value>, after: Option<serde_json::Value>) {
+    let action = match (&before, &after) {
+        (&None, &None) => { return; },
+        (&None, &Some(_)) => ACTION_CREATE,
+        (&Some(_), &None) => ACTION_DELETE,
+        (&Some(ref before), &Some(ref after)) => {
+            if before == after { return; }
+            ACTION_UPDATE
+        }
+    };
+    let new_audit_entry = models::dbo::NewAuditEntry {
+        created_at: tools::get_time(),
+        token_id: token.id,
+        token_name: token.name.clone(),
+        entity_type: entity_type.to_string(),
+        entity_key: entity_key.to_string(),
+        action: action.to_string(),
+        before_data: before.map(|before| before.to_string()),
+        after_data: after.map(|after| after.to_string()),
+    };
+    if let Err(error) = models::dbo::AuditEntry::create(&new_audit_entry, connection) {
+        log_error!("failed to record audit entry", "entity_type" => entity_type, "entity_key" => entity_key, "action" => action, "token" => token.name, "error" => error);
+    }
+}
+
+pub fn entry_info(audit_entry: &models::dbo::AuditEntry) -> models::json::AuditEntryInfo {
+    let parse = |data: &Option<String>| -> Option<serde_json::Value> {
+        match *data {
+            Some(ref data) => serde_json::from_str(data).ok(),
+            None => None,
+        }
+    };
+    return models::json::AuditEntryInfo {
+        id: audit_entry.id,
+        created_at: audit_entry.created_at,
+        token_id: audit_entry.token_id,
+        token_name: audit_entry.token_name.clone(),
+        entity_type: audit_entry.entity_type.clone(),
+        entity_key: audit_entry.entity_key.clone(),
+        action: audit_entry.action.clone(),
+        before: parse(&audit_entry.before_data),
+        after: parse(&audit_entry.after_data),
+    };
+}
@@ -0,0 +1,252 @@
+use models;
+use db;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use rocket::{Request, Response, State, Outcome};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest};
+use rocket::fairing::{Fairing, Info, Kind};
+use utilities;
+use rand;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+pub const SCOPE_READ: &str = "read";
+pub const SCOPE_REPORT: &str = "report";
+pub const SCOPE_DISCOVERY: &str = "discovery";
+pub const SCOPE_ADMIN: &str = "admin";
+pub const SCOPE_WEATHERMAP_EDIT: &str = "weathermap-edit";
+
+pub const SCOPES: [&str; 5] = [SCOPE_READ, SCOPE_REPORT, SCOPE_DISCOVERY, SCOPE_ADMIN, SCOPE_WEATHERMAP_EDIT];
+
+pub trait Scope {
+    const NAME: &'static str;
+    // Pollers report every few seconds, recording those would drown the actual changes
+    const AUDITED: bool;
+}
+
+pub struct ReadScope;
+pub struct ReportScope;
+pub struct DiscoveryScope;
+pub struct AdminScope;
+pub struct WeathermapEditScope;
+
+impl Scope for ReadScope { const NAME: &'static str = SCOPE_READ; const AUDITED: bool = false; }
+impl Scope for ReportScope { const NAME: &'static str = SCOPE_REPORT; const AUDITED: bool = false; }
+impl Scope for DiscoveryScope { const NAME: &'static str = SCOPE_DISCOVERY; const AUDITED: bool = true; }
+impl Scope for AdminScope { const NAME: &'static str = SCOPE_ADMIN; const AUDITED: bool = true; }
+impl Scope for WeathermapEditScope { const NAME: &'static str = SCOPE_WEATHERMAP_EDIT; const AUDITED: bool = true; }
+
+#[derive(Clone)]
+pub struct AuthenticatedToken {
+    // None for the bootstrap token from the configuration and when authentication is disabled
+    pub id: Option<i32>,
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+impl AuthenticatedToken {
+    pub fn has_scope(self: &AuthenticatedToken, scope: &str) -> bool {
+        return self.scopes.iter().any(|token_scope| token_scope == scope || token_scope == SCOPE_ADMIN);
+    }
+}
+
+pub struct Authorized<S: Scope> {
+    pub token: AuthenticatedToken,
+    scope: PhantomData<S>,
+}
+
+// Valid tokens by hash, pollers authenticate every report and those routes must not need the database.
+// Token routes invalidate entries on create and revoke, revocations made directly in the database
+// are only seen after a restart.
+pub struct TokenCache {
+    tokens: HashMap<String, AuthenticatedToken>,
+}
+
+impl TokenCache {
+    pub fn new() -> TokenCache {
+        return TokenCache {
+            tokens: HashMap::new(),
+        };
+    }
+
+    fn get(self: &TokenCache, token_hash: &String) -> Option<AuthenticatedToken> {
+        return self.tokens.get(token_hash).cloned();
+    }
+
+    fn insert(self: &mut TokenCache, token_hash: String, token: AuthenticatedToken) {
+        self.tokens.insert(token_hash, token);
+    }
+
+    pub fn invalidate(self: &mut TokenCache, token_hash: &String) {
+        self.tokens.remove(token_hash);
+    }
+}
+
+// Stored in the request local cache by the guard so that TokenAuditFairing knows who made the request
+#[derive(Clone)]
+struct AuditedRequest(Option<AuthenticatedToken>);
+
+pub fn generate_token() -> String {
+    let mut token_bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token_bytes);
+    return format!("jnx_{}", hex(&token_bytes));
+}
+
+pub fn hash_token(token: &str) -> String {
+    return hex(&Sha256::digest(token.as_bytes()));
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex_string = String::with_capacity(bytes.len() * 2);
+    for byte in bytes.iter() {
+        hex_string.push_str(&format!("{:02x}", byte));
+    }
+    return hex_string;
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() { return false; }
+    let mut difference : u8 = 0;
+    for (x, y) in a.bytes().zip(b.bytes()) {
+        difference |= x ^ y;
+    }
+    return difference == 0;
+}
+
+fn authenticate(request: &Request) -> Result<AuthenticatedToken, Status> {
+    let config = match request.guard::<State<utilities::config::Config>>() {
+        Outcome::Success(config) => config,
+        _ => { return Err(Status::InternalServerError); }
+    };
+    if !config.auth.enabled {
+        return Ok(AuthenticatedToken {
+            id: None,
+            name: "anonymous".to_string(),
+            scopes: vec![SCOPE_ADMIN.to_string()],
+        });
+    }
+
+    let presented_token = match request.headers().get_one("Authorization") {
+        Some(authorization) if authorization.starts_with("Bearer ") => authorization[7..].trim().to_string(),
+        _ => { return Err(Status::Unauthorized); }
+    };
+    if let Some(ref bootstrap_token) = config.auth.bootstrap_token {
+        if constant_time_eq(bootstrap_token, &presented_token) {
+            return Ok(AuthenticatedToken {
+                id: None,
+                name: "bootstrap".to_string(),
+                scopes: vec![SCOPE_ADMIN.to_string()],
+            });
+        }
+    }
+
+    let token_hash = hash_token(&presented_token);
+    let token_cache = match request.guard::<State<Arc<Mutex<TokenCache>>>>() {
+        Outcome::Success(token_cache) => token_cache,
+        _ => { return Err(Status::InternalServerError); }
+    };
+    if let Ok(ref token_cache) = token_cache.lock() {
+        if let Some(token) = token_cache.get(&token_hash) {
+            return Ok(token);
+        }
+    }
+
+    let pool = match request.guard::<State<db::Pool>>() {
+        Outcome::Success(pool) => pool,
+        _ => { return Err(Status::InternalServerError); }
+    };
+    let connection = match pool.get() {
+        Ok(connection) => connection,
+        Err(_) => { return Err(Status::ServiceUnavailable); }
+    };
+    match models::dbo::ApiToken::by_hash(&token_hash, &connection) {
+        Ok(Some(api_token)) => {
+            if api_token.revoked {
+                return Err(Status::Unauthorized);
+            }
+            let token = AuthenticatedToken {
+                id: Some(api_token.id),
+                scopes: api_token.scope_list(),
+                name: api_token.name,
+            };
+            if let Ok(ref mut token_cache) = token_cache.lock() {
+                token_cache.insert(token_hash, token.clone());
+            }
+            return Ok(token);
+        },
+        Ok(None) => {
+            return Err(Status::Unauthorized);
+        },
+        Err(error) => {
+            log_error!("failed to look up API token", "error" => error);
+            return Err(Status::ServiceUnavailable);
+        }
+    }
+}
+
+impl<'a, 'r, S: Scope> FromRequest<'a, 'r> for Authorized<S> {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Authorized<S>, ()> {
+        match authenticate(request) {
+            Ok(token) => {
+                if !token.has_scope(S::NAME) {
+                    log_warn!("API token lacks scope", "token" => token.name, "scope" => S::NAME, "uri" => request.uri());
+                    return Outcome::Failure((Status::Forbidden, ()));
+                }
+                if S::AUDITED {
+                    request.local_cache(|| AuditedRequest(Some(token.clone())));
+                }
+                return Outcome::Success(Authorized {
+                    token: token,
+                    scope: PhantomData,
+                });
+            },
+            Err(status) => {
+                return Outcome::Failure((status, ()));
+            }
+        }
+    }
+}
+
+pub struct TokenAuditFairing;
+
+impl Fairing for TokenAuditFairing {
+    fn info(&self) -> Info {
+        return Info {
+            name: "API token audit",
+            kind: Kind::Response,
+        };
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let token = match request.local_cache(|| AuditedRequest(None)).0 {
+            Some(ref token) => token.clone(),
+            None => { return; }
+        };
+        let pool = match request.guard::<State<db::Pool>>() {
+            Outcome::Success(pool) => pool,
+            _ => { return; }
+        };
+        let new_api_token_usage = models::dbo::NewApiTokenUsage {
+            token_id: token.id,
+            token_name: token.name.clone(),
+            used_at: utilities::tools::get_time(),
+            method: request.method().as_str().to_string(),
+            uri: format!("{}", request.uri()),
+            status: response.status().code as i32,
+        };
+        match pool.get() {
+            Ok(connection) => {
+                if let Err(error) = models::dbo::ApiTokenUsage::create(&new_api_token_usage, &connection) {
+                    log_error!("failed to record API token usage", "token" => token.name, "uri" => new_api_token_usage.uri, "error" => error);
+                }
+            },
+            Err(error) => {
+                log_error!("failed to record API token usage", "token" => token.name, "uri" => new_api_token_usage.uri, "error" => error);
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use models;
+use db;
+use serde_json;
+use std::fs;
+use std::io::{self, Read, Write};
+use diesel::Connection;
+use diesel::pg::PgConnection;
+use utilities;
+use utilities::tools;
+
+pub const BACKUP_VERSION: u32 = 1;
+
+fn current_schema_version(connection: &PgConnection) -> Result<String, utilities::errors::ApiError> {
+    match models::dbo::schema_version(connection)? {
+        Some(schema_version) => {
+            return Ok(schema_version);
+        },
+        None => {
+            return Err(utilities::errors::ApiError::Unavailable("database has no migrations applied".to_string()));
+        }
+    }
+}
+
+// API tokens, their usage and the audit log belong to the installation and are left out
+pub fn create(connection: &PgConnection) -> Result<models::json::BackupArchive, utilities::errors::ApiError> {
+    // One snapshot of every table, rows written meanwhile must not leave dangling references.
+    // READ COMMITTED would give every query its own snapshot.
+    let archive = connection.build_transaction().repeatable_read().read_only().run::<models::json::BackupArchive, utilities::errors::ApiError, _>(|| {
+        return Ok(models::json::BackupArchive {
+            version: BACKUP_VERSION,
+            schema_version: current_schema_version(connection)?,
+            created_at: tools::get_time(),
+            credential_profiles: models::dbo::CredentialProfile::load_all(connection)?,
+            devices: models::dbo::Device::load_all(connection)?,
+            device_credentials: models::dbo::DeviceCredential::load_all(connection)?,
+            interfaces: models::dbo::Interface::load_all(connection)?,
+            weathermap_device_infos: models::dbo::WeathermapDeviceInfo::load_all(connection)?,
+            client_locations: models::dbo::ClientLocation::load_all(connection)?,
+            topology_snapshots: models::dbo::TopologySnapshot::load_all(connection)?,
+        });
+    })?;
+    // Plaintext rows left over from before the credentials migration would make the archive unrestorable
+    for device_credential in archive.device_credentials.iter() {
+        if let Some(ref snmp_community) = device_credential.snmp_community {
+            if !utilities::credentials::CredentialCipher::is_encrypted(snmp_community) {
+                return Err(utilities::errors::ApiError::Conflict(format!("device {} has an unencrypted SNMP community", device_credential.device_id)));
+            }
+        }
+    }
+    return Ok(archive);
+}
+
+// Stored credentials are only usable with the key that encrypted them
+fn check_credentials(archive: &models::json::BackupArchive, cipher: &utilities::credentials::CredentialCipher) -> Result<(), String> {
+    for device_credential in archive.device_credentials.iter() {
+        cipher.decrypt_optional(&device_credential.snmp_community).map_err(|error| format!("device {} credentials: {}", device_credential.device_id, error))?;
+    }
+    for credential_profile in archive.credential_profiles.iter() {
+        utilities::credentials::snmp_credentials(credential_profile, cipher).map_err(|error| format!("credential profile {}: {}", credential_profile.name, error))?;
+    }
+    return Ok(());
+}
+
+// Restores into a database that has the same migrations applied and no data yet, all or nothing
+pub fn restore(archive: &models::json::BackupArchive, cipher: &utilities::credentials::CredentialCipher, connection: &PgConnection) -> Result<(), utilities::errors::ApiError> {
+    if archive.version != BACKUP_VERSION {
+        return Err(utilities::errors::ApiError::BadRequest(format!("unsupported backup version {}, expected {}", archive.version, BACKUP_VERSION)));
+    }
+    check_credentials(archive, cipher).map_err(utilities::errors::ApiError::BadRequest)?;
+    return connection.transaction::<(), utilities::errors::ApiError, _>(|| {
+        let schema_version = current_schema_version(connection)?;
+        if schema_version != archive.schema_version {
+            return Err(utilities::errors::ApiError::Conflict(format!("backup is from schema version {}, database is at {}", archive.schema_version, schema_version)));
+        }
+        if !models::dbo::backup_tables_empty(connection)? {
+            return Err(utilities::errors::ApiError::Conflict("database is not empty".to_string()));
+        }
+        models::dbo::CredentialProfile::restore(&archive.credential_profiles, connection)?;
+        models::dbo::Device::restore(&archive.devices, connection)?;
+        models::dbo::DeviceCredential::restore(&archive.device_credentials, connection)?;
+
+        // Interfaces reference each other, so the references are set once all of them exist
+        let mut unlinked_interfaces : Vec<models::dbo::Interface> = archive.interfaces.clone();
+        for interface in unlinked_interfaces.iter_mut() {
+            interface.connected_interface = None;
+            interface.virtual_connection = None;
+            interface.aggregate_interface = None;
+        }
+        models::dbo::Interface::restore(&unlinked_interfaces, connection)?;
+        for interface in archive.interfaces.iter() {
+            if interface.connected_interface.is_some() || interface.virtual_connection.is_some() || interface.aggregate_interface.is_some() {
+                interface.update(connection)?;
+            }
+        }
+
+        models::dbo::WeathermapDeviceInfo::restore(&archive.weathermap_device_infos, connection)?;
+        models::dbo::ClientLocation::restore(&archive.client_locations, connection)?;
+        models::dbo::TopologySnapshot::restore(&archive.topology_snapshots, connection)?;
+        models::dbo::reset_backup_sequences(connection)?;
+        return Ok(());
+    });
+}
+
+fn backup_command(path: Option<&String>, cipher: &utilities::credentials::CredentialCipher, connection: &PgConnection) -> Result<(), String> {
+    // The server does this at startup, a backup may be taken before it ever ran on a migrated database
+    utilities::credentials::encrypt_legacy_credentials(cipher, connection);
+    let archive = create(connection).map_err(|error| error.message())?;
+    let data = serde_json::to_string(&archive).map_err(|error| format!("{}", error))?;
+    match path {
+        Some(path) if path != "-" => {
+            fs::write(path, data).map_err(|error| format!("{}: {}", path, error))?;
+        },
+        _ => {
+            io::stdout().write_all(data.as_bytes()).map_err(|error| format!("{}", error))?;
+        }
+    }
+    log_info!("backup created", "devices" => archive.devices.len(), "interfaces" => archive.interfaces.len(), "schema_version" => archive.schema_version);
+    return Ok(());
+}
+
+fn restore_command(path: Option<&String>, cipher: &utilities::credentials::CredentialCipher, connection: &PgConnection) -> Result<(), String> {
+    let mut data = String::new();
+    match path {
+        Some(path) if path != "-" => {
+            data = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        },
+        _ => {
+            io::stdin().read_to_string(&mut data).map_err(|error| format!("{}", error))?;
+        }
+    }
+    let archive : models::json::BackupArchive = serde_json::from_str(&data).map_err(|error| format!("invalid backup: {}", error))?;
+    restore(&archive, cipher, connection).map_err(|error| error.message())?;
+    log_info!("backup restored", "devices" => archive.devices.len(), "interfaces" => archive.interfaces.len(), "schema_version" => archive.schema_version);
+    return Ok(());
+}
+
+// `backup [file]` and `restore [file]` run instead of the server, stdout and stdin are used
+// without a file or with `-`. Returns the process exit code.
+pub fn run_command(args: &[String], config: &utilities::config::Config, cipher: &utilities::credentials::CredentialCipher) -> i32 {
+    let pool = db::connect(config);
+    let connection = match pool.get() {
+        Ok(connection) => connection,
+        Err(error) => {
+            log_error!("could not get a database connection", "command" => args.join(" "), "error" => error);
+            return 1;
+        }
+    };
+    let result = match args.first().map(|command| command.as_str()) {
+        Some("backup") => backup_command(args.get(1), cipher, &connection),
+        Some("restore") => restore_command(args.get(1), cipher, &connection),
+        _ => Err(format!("unknown command {}, expected backup or restore", args.join(" "))),
+    };
+    match result {
+        Ok(_) => {
+            return 0;
+        },
+        Err(error) => {
+            log_error!("command failed", "command" => args.join(" "), "error" => error);
+            return 1;
+        }
+    }
+}
@@ -1,32 +1,60 @@
 use std::sync::{Arc, Mutex};
-use models::json;
+use std::io::Cursor;
 use utilities::tools;
+use utilities::topology::TopologyGraph;
+use diesel::pg::PgConnection;
 use std::ops::DerefMut;
+use rocket::{Request, Response, Outcome};
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest};
+use rocket::response::{self, Responder};
 
 pub struct CachedWeathermapTopology {
     pub valid_until: f64,
-    pub weathermap_topology: json::WeathermapBase,
+    pub version: u64,
+    pub serialized_topology: String,
 }
 
 impl CachedWeathermapTopology {
-    pub fn new(topology: json::WeathermapBase) -> CachedWeathermapTopology {
+    pub fn new(version: u64, serialized_topology: String, ttl: f64) -> CachedWeathermapTopology {
         return CachedWeathermapTopology {
-            // TODO: configurable cache time :)
-            valid_until: tools::get_time() + 30.0,
-            weathermap_topology: topology
+            valid_until: tools::get_time() + ttl,
+            version: version,
+            serialized_topology: serialized_topology,
         };
     }
 }
 
+pub struct CachedTopologyGraph {
+    pub valid_until: f64,
+    pub topology_graph: Arc<TopologyGraph>,
+}
+
 pub struct CacheController {
-    pub cached_weathermap_topology: Arc<Mutex<Option<CachedWeathermapTopology>>>
+    pub weathermap_cache_ttl: f64,
+    pub cached_weathermap_topology: Arc<Mutex<Option<CachedWeathermapTopology>>>,
+    pub cached_topology_graph: Arc<Mutex<Option<CachedTopologyGraph>>>,
 }
 
 impl CacheController {
-    pub fn new() -> CacheController {
+    // Topology changes invalidate the cache, the TTL only covers changes made behind our back
+    pub fn new(weathermap_cache_ttl: f64) -> CacheController {
         return CacheController {
+            weathermap_cache_ttl: weathermap_cache_ttl,
             cached_weathermap_topology: Arc::new(Mutex::new(None)),
+            cached_topology_graph: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn topology_graph(self: &CacheController, connection: &PgConnection) -> Arc<TopologyGraph> {
+        return load_topology_graph(&self.cached_topology_graph, self.weathermap_cache_ttl, connection);
+    }
+
+    pub fn invalidate_topology_cache(self: &CacheController) {
+        if let Ok(ref mut cached_topology_graph_option_mutex) = self.cached_topology_graph.lock() {
+            *cached_topology_graph_option_mutex.deref_mut() = None;
         }
+        self.invalidate_weathermap_cache();
     }
 
     pub fn invalidate_weathermap_cache(self: &CacheController) {
@@ -37,4 +65,72 @@ impl CacheController {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Shared by the weathermap, topology queries and IMDS event enrichment, loaded in bulk on a miss.
+// Takes the inner cache directly so callers can avoid holding the CacheController lock during the load.
+pub fn load_topology_graph(cached_topology_graph: &Arc<Mutex<Option<CachedTopologyGraph>>>, ttl: f64, connection: &PgConnection) -> Arc<TopologyGraph> {
+    if let Ok(ref mut cached_topology_graph_option_mutex) = cached_topology_graph.lock() {
+        let cached_topology_graph_option: &mut Option<CachedTopologyGraph> = cached_topology_graph_option_mutex.deref_mut();
+        if let Some(ref cached_topology_graph_data) = *cached_topology_graph_option {
+            if tools::get_time() < cached_topology_graph_data.valid_until {
+                return cached_topology_graph_data.topology_graph.clone();
+            }
+        }
+        let topology_graph = Arc::new(TopologyGraph::load(connection));
+        *cached_topology_graph_option = Some(CachedTopologyGraph {
+            valid_until: tools::get_time() + ttl,
+            topology_graph: topology_graph.clone(),
+        });
+        return topology_graph;
+    }
+    return Arc::new(TopologyGraph::load(connection));
+}
+
+pub struct IfNoneMatch(pub Option<String>);
+
+impl IfNoneMatch {
+    pub fn matches(self: &IfNoneMatch, etag: &String) -> bool {
+        if let Some(ref if_none_match) = self.0 {
+            for candidate in if_none_match.split(',') {
+                let candidate = candidate.trim();
+                let candidate = if candidate.starts_with("W/") { &candidate[2..] } else { candidate };
+                if candidate == "*" || candidate == etag { return true; }
+            }
+        }
+        return false;
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<IfNoneMatch, ()> {
+        return Outcome::Success(IfNoneMatch(request.headers().get_one("If-None-Match").map(|value| value.to_string())));
+    }
+}
+
+pub enum CachedJsonResponse {
+    NotModified(String),
+    Modified(String, String),
+}
+
+impl<'r> Responder<'r> for CachedJsonResponse {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        match self {
+            CachedJsonResponse::NotModified(etag) => {
+                return Response::build()
+                    .status(Status::NotModified)
+                    .raw_header("ETag", etag)
+                    .ok();
+            },
+            CachedJsonResponse::Modified(etag, body) => {
+                return Response::build()
+                    .header(ContentType::JSON)
+                    .raw_header("ETag", etag)
+                    .sized_body(Cursor::new(body))
+                    .ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::fmt::Display;
+use utilities::logging::LogLevel;
+use utilities::credentials;
+extern crate toml;
+
+const DEFAULT_CONFIG_PATH: &str = "jaspy-nexus.toml";
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    pub pool_size: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        return DatabaseConfig {
+            url: None,
+            pool_size: 10,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BusConfig {
+    pub event_publish: Option<String>,
+}
+
+impl Default for BusConfig {
+    fn default() -> BusConfig {
+        return BusConfig {
+            event_publish: None,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImdsConfig {
+    pub expiry: f64,
+}
+
+impl Default for ImdsConfig {
+    fn default() -> ImdsConfig {
+        return ImdsConfig {
+            expiry: 60.0,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerConfig {
+    pub tick: f64,
+    pub full_refresh_iterations: u32,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> WorkerConfig {
+        return WorkerConfig {
+            tick: 1.0,
+            full_refresh_iterations: 10,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub topology_ttl: f64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        return CacheConfig {
+            topology_ttl: 30.0,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub address: String,
+    pub port: u16,
+    pub workers: Option<u16>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        return HttpConfig {
+            address: "localhost".to_string(),
+            port: 8000,
+            workers: None,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub level: String,
+    pub format: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> LoggingConfig {
+        return LoggingConfig {
+            level: "info".to_string(),
+            format: "text".to_string(),
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopologyConfig {
+    pub roots: Vec<String>,
+    pub snapshot_interval: f64,
+}
+
+impl Default for TopologyConfig {
+    fn default() -> TopologyConfig {
+        return TopologyConfig {
+            roots: Vec::new(),
+            snapshot_interval: 3600.0,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub worker_max_age: f64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> HealthConfig {
+        return HealthConfig {
+            worker_max_age: 10.0,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub enabled: bool,
+    // Grants the admin scope, meant for creating the first tokens
+    pub bootstrap_token: Option<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        return AuthConfig {
+            enabled: true,
+            bootstrap_token: None,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CredentialsConfig {
+    // Base64 encoded 32 byte key used to encrypt SNMP credentials at rest
+    pub key: Option<String>,
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> CredentialsConfig {
+        return CredentialsConfig {
+            key: None,
+        };
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub bus: BusConfig,
+    pub imds: ImdsConfig,
+    pub worker: WorkerConfig,
+    pub cache: CacheConfig,
+    pub http: HttpConfig,
+    pub logging: LoggingConfig,
+    pub topology: TopologyConfig,
+    pub health: HealthConfig,
+    pub auth: AuthConfig,
+    pub credentials: CredentialsConfig,
+}
+
+fn env_override<T: FromStr>(name: &str, target: &mut T) -> Result<(), String> where T::Err: Display {
+    if let Ok(value) = env::var(name) {
+        match value.parse::<T>() {
+            Ok(parsed) => {
+                *target = parsed;
+            },
+            Err(error) => {
+                return Err(format!("{}: invalid value {:?}: {}", name, value, error));
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn env_override_string(name: &str, target: &mut Option<String>) {
+    if let Ok(value) = env::var(name) {
+        *target = Some(value);
+    }
+}
+
+impl Config {
+    // The file is optional, JASPY_NEXUS_CONFIG points to another location and then it must exist.
+    // Environment variables override file values, both are validated together afterwards.
+    pub fn load() -> Result<Config, String> {
+        let mut config : Config;
+        let config_path : String;
+        let required : bool;
+        match env::var("JASPY_NEXUS_CONFIG") {
+            Ok(env_opt) => {
+                config_path = env_opt;
+                required = true;
+            },
+            Err(_) => {
+                config_path = DEFAULT_CONFIG_PATH.to_string();
+                required = false;
+            }
+        }
+        if required || Path::new(&config_path).exists() {
+            let contents = fs::read_to_string(&config_path)
+                .map_err(|error| format!("{}: {}", config_path, error))?;
+            config = toml::from_str::<Config>(&contents)
+                .map_err(|error| format!("{}: {}", config_path, error))?;
+        } else {
+            config = Config::default();
+        }
+
+        config.apply_env()?;
+        config.validate()?;
+        return Ok(config);
+    }
+
+    fn apply_env(self: &mut Config) -> Result<(), String> {
+        env_override_string("DATABASE_URL", &mut self.database.url);
+        env_override("DATABASE_POOL_SIZE", &mut self.database.pool_size)?;
+        env_override_string("EVENT_PUBLISH", &mut self.bus.event_publish);
+        env_override("IMDS_EXPIRY", &mut self.imds.expiry)?;
+        env_override("WORKER_TICK", &mut self.worker.tick)?;
+        env_override("WORKER_FULL_REFRESH_ITERATIONS", &mut self.worker.full_refresh_iterations)?;
+        env_override("WEATHERMAP_CACHE_TTL", &mut self.cache.topology_ttl)?;
+        env_override("HTTP_ADDRESS", &mut self.http.address)?;
+        env_override("HTTP_PORT", &mut self.http.port)?;
+        env_override("LOG_LEVEL", &mut self.logging.level)?;
+        env_override("LOG_FORMAT", &mut self.logging.format)?;
+        env_override("TOPOLOGY_SNAPSHOT_INTERVAL", &mut self.topology.snapshot_interval)?;
+        env_override("HEALTH_WORKER_MAX_AGE", &mut self.health.worker_max_age)?;
+        env_override("AUTH_ENABLED", &mut self.auth.enabled)?;
+        env_override_string("AUTH_BOOTSTRAP_TOKEN", &mut self.auth.bootstrap_token);
+        env_override_string("CREDENTIALS_KEY", &mut self.credentials.key);
+        if let Ok(env_opt) = env::var("TOPOLOGY_ROOTS") {
+            self.topology.roots = env_opt.split(',').map(|fqdn| fqdn.trim().to_string()).filter(|fqdn| !fqdn.is_empty()).collect();
+        }
+        return Ok(());
+    }
+
+    fn validate(self: &Config) -> Result<(), String> {
+        match self.database.url {
+            Some(ref url) if !url.is_empty() => {},
+            _ => { return Err("database.url (DATABASE_URL) must be set".to_string()); }
+        }
+        if self.database.pool_size == 0 {
+            return Err("database.pool_size (DATABASE_POOL_SIZE) must be at least 1".to_string());
+        }
+        match self.bus.event_publish {
+            Some(ref event_publish) if !event_publish.is_empty() => {},
+            _ => { return Err("bus.event_publish (EVENT_PUBLISH) must be set".to_string()); }
+        }
+        if !(self.imds.expiry > 0.0) {
+            return Err("imds.expiry (IMDS_EXPIRY) must be a positive number of seconds".to_string());
+        }
+        if !(self.worker.tick > 0.0) {
+            return Err("worker.tick (WORKER_TICK) must be a positive number of seconds".to_string());
+        }
+        if self.worker.full_refresh_iterations == 0 {
+            return Err("worker.full_refresh_iterations (WORKER_FULL_REFRESH_ITERATIONS) must be at least 1".to_string());
+        }
+        if !(self.cache.topology_ttl >= 0.0) {
+            return Err("cache.topology_ttl (WEATHERMAP_CACHE_TTL) must not be negative".to_string());
+        }
+        if self.http.address.is_empty() {
+            return Err("http.address (HTTP_ADDRESS) must not be empty".to_string());
+        }
+        if self.http.workers == Some(0) {
+            return Err("http.workers must be at least 1".to_string());
+        }
+        if LogLevel::parse(&self.logging.level).is_none() {
+            return Err(format!("logging.level (LOG_LEVEL) must be one of error, warn, info, debug, not {:?}", self.logging.level));
+        }
+        let log_format = self.logging.format.to_lowercase();
+        if log_format != "text" && log_format != "json" {
+            return Err(format!("logging.format (LOG_FORMAT) must be either text or json, not {:?}", self.logging.format));
+        }
+        if !(self.health.worker_max_age > 0.0) {
+            return Err("health.worker_max_age (HEALTH_WORKER_MAX_AGE) must be a positive number of seconds".to_string());
+        }
+        if let Some(ref bootstrap_token) = self.auth.bootstrap_token {
+            if bootstrap_token.len() < 16 {
+                return Err("auth.bootstrap_token (AUTH_BOOTSTRAP_TOKEN) must be at least 16 characters".to_string());
+            }
+        }
+        match self.credentials.key {
+            Some(ref key) if !key.is_empty() => {
+                if let Err(error) = credentials::decode_key(key) {
+                    return Err(format!("credentials.key (CREDENTIALS_KEY) {}", error));
+                }
+            },
+            _ => { return Err("credentials.key (CREDENTIALS_KEY) must be set, generate one with `openssl rand -base64 32`".to_string()); }
+        }
+        return Ok(());
+    }
+
+    pub fn database_url(self: &Config) -> String {
+        return self.database.url.clone().unwrap_or_default();
+    }
+
+    pub fn event_publish(self: &Config) -> String {
+        return self.bus.event_publish.clone().unwrap_or_default();
+    }
+
+    pub fn credentials_key(self: &Config) -> String {
+        return self.credentials.key.clone().unwrap_or_default();
+    }
+}
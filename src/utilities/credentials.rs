@@ -0,0 +1,253 @@
+use models;
+use utilities;
+use diesel::pg::PgConnection;
+use rand;
+use rand::RngCore;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, KeyInit};
+
+// Versioned so that the algorithm or key can be rotated later without guessing what a value is
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LENGTH: usize = 12;
+pub const KEY_LENGTH: usize = 32;
+
+pub const SNMP_VERSIONS: [&str; 3] = ["1", "2c", "3"];
+pub const SNMP_AUTH_PROTOCOLS: [&str; 6] = ["MD5", "SHA", "SHA-224", "SHA-256", "SHA-384", "SHA-512"];
+pub const SNMP_PRIV_PROTOCOLS: [&str; 4] = ["DES", "AES", "AES-192", "AES-256"];
+// RFC 3414 minimum for USM passwords
+const SNMP_MIN_PASSWORD_LENGTH: usize = 8;
+
+pub fn decode_key(key: &str) -> Result<Vec<u8>, String> {
+    let key_bytes = BASE64.decode(key.trim()).map_err(|error| format!("not valid base64: {}", error))?;
+    if key_bytes.len() != KEY_LENGTH {
+        return Err(format!("must decode to {} bytes, got {}", KEY_LENGTH, key_bytes.len()));
+    }
+    return Ok(key_bytes);
+}
+
+#[derive(Clone)]
+pub struct CredentialCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl CredentialCipher {
+    pub fn new(key: &str) -> Result<CredentialCipher, String> {
+        let key_bytes = decode_key(key)?;
+        return Ok(CredentialCipher {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key_bytes)),
+        });
+    }
+
+    pub fn is_encrypted(stored: &str) -> bool {
+        return stored.starts_with(ENCRYPTED_PREFIX);
+    }
+
+    pub fn encrypt(self: &CredentialCipher, plaintext: &str) -> Result<String, String> {
+        let mut nonce_bytes = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_bytes())
+            .map_err(|_| "encryption failed".to_string())?;
+        let mut sealed : Vec<u8> = nonce_bytes.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        return Ok(format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(&sealed)));
+    }
+
+    pub fn decrypt(self: &CredentialCipher, stored: &str) -> Result<String, String> {
+        if !CredentialCipher::is_encrypted(stored) {
+            return Err("credential is not encrypted".to_string());
+        }
+        let sealed = BASE64.decode(&stored[ENCRYPTED_PREFIX.len()..]).map_err(|error| format!("credential is not valid base64: {}", error))?;
+        if sealed.len() <= NONCE_LENGTH {
+            return Err("credential is truncated".to_string());
+        }
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(&sealed[..NONCE_LENGTH]), &sealed[NONCE_LENGTH..])
+            .map_err(|_| "credential cannot be decrypted with the configured key".to_string())?;
+        return String::from_utf8(plaintext).map_err(|_| "credential is not valid UTF-8".to_string());
+    }
+
+    pub fn encrypt_optional(self: &CredentialCipher, plaintext: &Option<String>) -> Result<Option<String>, String> {
+        match *plaintext {
+            Some(ref plaintext) => {
+                return Ok(Some(self.encrypt(plaintext)?));
+            },
+            None => {
+                return Ok(None);
+            }
+        }
+    }
+
+    pub fn decrypt_optional(self: &CredentialCipher, stored: &Option<String>) -> Result<Option<String>, String> {
+        match *stored {
+            Some(ref stored) => {
+                return Ok(Some(self.decrypt(stored)?));
+            },
+            None => {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+// Returns whether the stored community changed. Callers must not raise events for this, the
+// event bus is readable by everyone subscribed to it.
+pub fn store_snmp_community(device: &models::dbo::Device, snmp_community: &Option<String>, cipher: &CredentialCipher, connection: &PgConnection) -> Result<bool, utilities::errors::ApiError> {
+    let existing = models::dbo::DeviceCredential::by_device(device.id, connection);
+    if let Some(ref existing) = existing {
+        if let Ok(current) = cipher.decrypt_optional(&existing.snmp_community) {
+            if current == *snmp_community { return Ok(false); }
+        }
+    } else if snmp_community.is_none() {
+        return Ok(false);
+    }
+
+    let encrypted : Option<String> = match *snmp_community {
+        Some(ref snmp_community) => {
+            match cipher.encrypt(snmp_community) {
+                Ok(encrypted) => Some(encrypted),
+                Err(error) => {
+                    return Err(utilities::errors::ApiError::Internal(format!("failed to encrypt SNMP community: {}", error)));
+                }
+            }
+        },
+        None => None,
+    };
+    models::dbo::DeviceCredential::set_snmp_community(device.id, encrypted, connection)?;
+    return Ok(true);
+}
+
+fn non_empty(value: &Option<String>) -> bool {
+    match *value {
+        Some(ref value) => !value.is_empty(),
+        None => false,
+    }
+}
+
+pub fn validate_profile(profile_request: &models::json::CredentialProfileRequest) -> Result<(), String> {
+    if profile_request.name.trim().is_empty() {
+        return Err("profile name must not be empty".to_string());
+    }
+    if !SNMP_VERSIONS.contains(&profile_request.version.as_str()) {
+        return Err(format!("unknown SNMP version {}, expected one of {}", profile_request.version, SNMP_VERSIONS.join(", ")));
+    }
+    if profile_request.version != "3" {
+        if !non_empty(&profile_request.community) {
+            return Err(format!("SNMP version {} requires a community", profile_request.version));
+        }
+        if profile_request.username.is_some() || profile_request.auth_protocol.is_some() || profile_request.priv_protocol.is_some() {
+            return Err(format!("SNMP version {} does not use a user, auth or priv settings", profile_request.version));
+        }
+        return Ok(());
+    }
+
+    if profile_request.community.is_some() {
+        return Err("SNMP version 3 does not use a community".to_string());
+    }
+    if !non_empty(&profile_request.username) {
+        return Err("SNMP version 3 requires a username".to_string());
+    }
+    match profile_request.auth_protocol {
+        Some(ref auth_protocol) => {
+            if !SNMP_AUTH_PROTOCOLS.contains(&auth_protocol.as_str()) {
+                return Err(format!("unknown auth protocol {}, expected one of {}", auth_protocol, SNMP_AUTH_PROTOCOLS.join(", ")));
+            }
+            match profile_request.auth_password {
+                Some(ref auth_password) if auth_password.len() >= SNMP_MIN_PASSWORD_LENGTH => {},
+                _ => { return Err(format!("auth password of at least {} characters is required", SNMP_MIN_PASSWORD_LENGTH)); }
+            }
+        },
+        None => {
+            if profile_request.auth_password.is_some() {
+                return Err("auth password given without an auth protocol".to_string());
+            }
+        }
+    }
+    match profile_request.priv_protocol {
+        Some(ref priv_protocol) => {
+            // noAuthPriv is not a valid USM security level
+            if profile_request.auth_protocol.is_none() {
+                return Err("priv protocol requires an auth protocol".to_string());
+            }
+            if !SNMP_PRIV_PROTOCOLS.contains(&priv_protocol.as_str()) {
+                return Err(format!("unknown priv protocol {}, expected one of {}", priv_protocol, SNMP_PRIV_PROTOCOLS.join(", ")));
+            }
+            match profile_request.priv_password {
+                Some(ref priv_password) if priv_password.len() >= SNMP_MIN_PASSWORD_LENGTH => {},
+                _ => { return Err(format!("priv password of at least {} characters is required", SNMP_MIN_PASSWORD_LENGTH)); }
+            }
+        },
+        None => {
+            if profile_request.priv_password.is_some() {
+                return Err("priv password given without a priv protocol".to_string());
+            }
+        }
+    }
+    return Ok(());
+}
+
+// Expects a request that passed validate_profile
+pub fn new_credential_profile(profile_request: &models::json::CredentialProfileRequest, cipher: &CredentialCipher) -> Result<models::dbo::NewCredentialProfile, String> {
+    return Ok(models::dbo::NewCredentialProfile {
+        name: profile_request.name.trim().to_string(),
+        snmp_version: profile_request.version.clone(),
+        community: cipher.encrypt_optional(&profile_request.community)?,
+        username: profile_request.username.clone(),
+        auth_protocol: profile_request.auth_protocol.clone(),
+        auth_password: cipher.encrypt_optional(&profile_request.auth_password)?,
+        priv_protocol: profile_request.priv_protocol.clone(),
+        priv_password: cipher.encrypt_optional(&profile_request.priv_password)?,
+    });
+}
+
+pub fn snmp_credentials(credential_profile: &models::dbo::CredentialProfile, cipher: &CredentialCipher) -> Result<models::json::SnmpCredentials, String> {
+    return Ok(models::json::SnmpCredentials {
+        version: credential_profile.snmp_version.clone(),
+        community: cipher.decrypt_optional(&credential_profile.community)?,
+        username: credential_profile.username.clone(),
+        auth_protocol: credential_profile.auth_protocol.clone(),
+        auth_password: cipher.decrypt_optional(&credential_profile.auth_password)?,
+        priv_protocol: credential_profile.priv_protocol.clone(),
+        priv_password: cipher.decrypt_optional(&credential_profile.priv_password)?,
+    });
+}
+
+// Resolves a profile name from a device or discovery request
+pub fn credential_profile_id(name: &String, connection: &PgConnection) -> Result<i32, utilities::errors::ApiError> {
+    match models::dbo::CredentialProfile::by_name(name, connection) {
+        Some(credential_profile) => {
+            return Ok(credential_profile.id);
+        },
+        None => {
+            return Err(utilities::errors::ApiError::BadRequest(format!("unknown credential profile {}", name)));
+        }
+    }
+}
+
+// Rows copied over from devices.snmp_community by the migration are still plaintext
+pub fn encrypt_legacy_credentials(cipher: &CredentialCipher, connection: &PgConnection) {
+    let mut encrypted_count : usize = 0;
+    for mut device_credential in models::dbo::DeviceCredential::all(connection).into_iter() {
+        let plaintext = match device_credential.snmp_community {
+            Some(ref snmp_community) if !CredentialCipher::is_encrypted(snmp_community) => snmp_community.clone(),
+            _ => { continue; }
+        };
+        match cipher.encrypt(&plaintext) {
+            Ok(encrypted) => {
+                device_credential.snmp_community = Some(encrypted);
+                match device_credential.update(connection) {
+                    Ok(_) => { encrypted_count += 1; },
+                    Err(error) => {
+                        log_error!("failed to encrypt stored SNMP community", "device_id" => device_credential.device_id, "error" => error);
+                    }
+                }
+            },
+            Err(error) => {
+                log_error!("failed to encrypt stored SNMP community", "device_id" => device_credential.device_id, "error" => error);
+            }
+        }
+    }
+    if encrypted_count > 0 {
+        log_info!("encrypted plaintext SNMP communities", "count" => encrypted_count);
+    }
+}
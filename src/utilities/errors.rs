@@ -0,0 +1,93 @@
+use models;
+use diesel;
+use diesel::result::DatabaseErrorKind;
+use rocket::Request;
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket_contrib::json;
+
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    PayloadTooLarge(String),
+    Unavailable(String),
+    Internal(String),
+    Database(diesel::result::Error),
+}
+
+impl ApiError {
+    pub fn status(self: &ApiError) -> Status {
+        match *self {
+            ApiError::BadRequest(_) => Status::BadRequest,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Conflict(_) => Status::Conflict,
+            ApiError::PayloadTooLarge(_) => Status::PayloadTooLarge,
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+            ApiError::Internal(_) => Status::InternalServerError,
+            ApiError::Database(ref error) => {
+                match *error {
+                    diesel::result::Error::NotFound => Status::NotFound,
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => Status::Conflict,
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => Status::Conflict,
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _) => Status::ServiceUnavailable,
+                    _ => Status::InternalServerError,
+                }
+            }
+        }
+    }
+
+    pub fn message(self: &ApiError) -> String {
+        match *self {
+            ApiError::BadRequest(ref message) => message.clone(),
+            ApiError::NotFound(ref message) => message.clone(),
+            ApiError::Conflict(ref message) => message.clone(),
+            ApiError::PayloadTooLarge(ref message) => message.clone(),
+            ApiError::Unavailable(ref message) => message.clone(),
+            ApiError::Internal(ref message) => message.clone(),
+            ApiError::Database(ref error) => format!("{}", error),
+        }
+    }
+
+    // Database errors carry constraint and table names, clients only get told what kind of failure it was
+    pub fn public_message(self: &ApiError) -> String {
+        match *self {
+            ApiError::Database(ref error) => {
+                match *error {
+                    diesel::result::Error::NotFound => "not found".to_string(),
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => "conflicts with an existing entry".to_string(),
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => "conflicts with a referenced or referencing entry".to_string(),
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _) => "database unavailable".to_string(),
+                    _ => "database error".to_string(),
+                }
+            },
+            _ => self.message(),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(error: diesel::result::Error) -> ApiError {
+        return ApiError::Database(error);
+    }
+}
+
+pub fn error_body(status: Status, message: String) -> json::Json<models::json::ApiErrorBody> {
+    return json::Json(models::json::ApiErrorBody {
+        status: status.code,
+        error: status.reason.to_string(),
+        message: message,
+    });
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let status = self.status();
+        if let ApiError::Database(ref error) = self {
+            log_error!("database error", "uri" => request.uri(), "status" => status.code, "error" => error);
+        }
+        let mut response = error_body(status, self.public_message()).respond_to(request)?;
+        response.set_status(status);
+        return Ok(response);
+    }
+}
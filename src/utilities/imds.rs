@@ -2,63 +2,106 @@ use models;
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc,Mutex};
 use utilities;
-use db;
 
 pub struct IMDS {
     metrics_storage : models::metrics::Metrics,
-    msgbus: Arc<Mutex<utilities::msgbus::MessageBus>>
+    msgbus: Arc<Mutex<utilities::msgbus::MessageBus>>,
+    // Maintained by imds_worker so that reports never wait for the database while holding the lock
+    topology: Arc<utilities::topology::TopologyGraph>,
+    unknown_reports: HashMap<String, models::metrics::UnknownReportCounters>,
+    pruned_devices: u64,
+    pruned_interfaces: u64,
+    expiry: f64,
 }
 
 struct ConnectionPair {
-    local_device: models::dbo::Device,
+    local_device_id: i32,
     remote_info: Option<ConnectionPairRemoteInfo>,
 }
 
 struct ConnectionPairRemoteInfo {
-    device: models::dbo::Device,
-    interface: models::dbo::Interface,
+    device_id: i32,
+    fqdn: String,
+    interface_name: String,
 }
 
 impl ConnectionPair {
-    fn load_by_fqdn_ifindex(connection: &db::Connection, fqdn: &String, ifindex: &i32) -> Option<ConnectionPair> {
-        if let Some(local_device) = models::dbo::Device::find_by_fqdn(connection, fqdn) {
-            if let Some(local_interface) = local_device.interface_by_index(connection, ifindex) {
-                let connpair : ConnectionPair;
-                if let Some(remote_interface) = local_interface.peer_interface(connection) {
-                    let remote_device = remote_interface.device(connection);
-                    connpair = ConnectionPair {
-                        local_device: local_device,
-                        remote_info: Some(ConnectionPairRemoteInfo {
-                            device: remote_device,
-                            interface: remote_interface,
-                        }),
-                    };
-                } else {
-                    connpair = ConnectionPair {
-                        local_device: local_device,
-                        remote_info: None,
-                    };
-                }
+    fn lookup_by_fqdn_ifindex(topology: &utilities::topology::TopologyGraph, fqdn: &String, ifindex: &i32) -> Option<ConnectionPair> {
+        let local_device_id = topology.device_id_by_fqdn(fqdn)?;
+        let local_interface = topology.interface_by_index(local_device_id, *ifindex)?;
+        let mut connpair = ConnectionPair {
+            local_device_id: local_device_id,
+            remote_info: None,
+        };
+        if let Some(remote_interface) = topology.peer_interface(local_interface.id) {
+            if let Some(remote_fqdn) = topology.fqdn(remote_interface.device_id) {
+                connpair.remote_info = Some(ConnectionPairRemoteInfo {
+                    device_id: remote_interface.device_id,
+                    fqdn: remote_fqdn,
+                    interface_name: remote_interface.name(),
+                });
+            }
+        }
+        return Some(connpair);
+    }
+}
 
-                return Some(connpair);
+fn link_state_text(up: Option<bool>) -> String {
+    match up {
+        Some(value) => {
+            if value {
+                return "up".to_string();
+            } else {
+                return "down".to_string();
             }
+        },
+        None => {
+            return "unknown".to_string();
         }
-        return None;
     }
 }
 
+fn octet_rate(old_octets: Option<u64>, new_octets: Option<u64>, elapsed: f64) -> Option<f64> {
+    if let (Some(old_octets), Some(new_octets)) = (old_octets, new_octets) {
+        // Counter wrapped or device restarted, wait for the next sample
+        if new_octets < old_octets { return None; }
+        return Some(((new_octets - old_octets) as f64) * 8.0 / elapsed);
+    }
+    return None;
+}
+
 impl IMDS {
-    pub fn new(msgbus: Arc<Mutex<utilities::msgbus::MessageBus>>) -> IMDS {
+    pub fn new(msgbus: Arc<Mutex<utilities::msgbus::MessageBus>>, expiry: f64) -> IMDS {
         let imds = IMDS {
             metrics_storage: models::metrics::Metrics {
                 devices: HashMap::new()
             },
-            msgbus: msgbus
+            msgbus: msgbus,
+            topology: Arc::new(utilities::topology::TopologyGraph::empty()),
+            unknown_reports: HashMap::new(),
+            pruned_devices: 0,
+            pruned_interfaces: 0,
+            expiry: expiry,
         };
 
         return imds;
     }
 
+    // Senders are arbitrary, so counters go away with prune() once a name stops reporting
+    fn unknown_report_counters(self: &mut IMDS, device_fqdn: &String) -> &mut models::metrics::UnknownReportCounters {
+        let counters = self.unknown_reports.entry(device_fqdn.clone()).or_insert(models::metrics::UnknownReportCounters {
+            last_seen: 0.0,
+            device_reports: 0,
+            interface_reports: 0,
+        });
+        counters.last_seen = utilities::tools::get_time();
+        return counters;
+    }
+
+    pub fn set_topology(self: &mut IMDS, topology: Arc<utilities::topology::TopologyGraph>) {
+        self.topology = topology;
+    }
+
     pub fn prune(self: &mut IMDS) {
         let current_time = utilities::tools::get_time();
         let mut delete_device_keys: Vec<String> = Vec::new();
@@ -75,21 +118,72 @@ impl IMDS {
                 for iface_key in delete_ifindex_keys.iter() {
                     device_metrics.interfaces.remove(iface_key);
                 }
+                self.pruned_interfaces += delete_ifindex_keys.len() as u64;
             }
         }
         for device_key in delete_device_keys.iter() {
             self.metrics_storage.devices.remove(device_key);
         }
+        self.pruned_devices += delete_device_keys.len() as u64;
+        let expiry = self.expiry;
+        self.unknown_reports.retain(|_, unknown_report_counters| current_time <= unknown_report_counters.last_seen + expiry);
     }
 
     pub fn get_device(self: &IMDS, device_fqdn: &String) -> Option<&models::metrics::DeviceMetrics> {
         return self.metrics_storage.devices.get(device_fqdn);
     }
 
+    pub fn get_interface(self: &IMDS, device_fqdn: &String, if_index: i32) -> Option<&models::metrics::InterfaceMetrics> {
+        match self.metrics_storage.devices.get(device_fqdn) {
+            Some(device) => {
+                return device.interfaces.get(&if_index);
+            },
+            None => {
+                return None;
+            }
+        }
+    }
+
+    // Entries are refreshed from the database whether or not the poller reports them, only
+    // the last device report tells if the name is still in use
+    pub fn is_reporting(self: &IMDS, device_fqdn: &String) -> bool {
+        match self.metrics_storage.devices.get(device_fqdn) {
+            Some(device) => {
+                match device.last_report {
+                    Some(last_report) => {
+                        return utilities::tools::get_time() < last_report + self.expiry;
+                    },
+                    None => {
+                        return false;
+                    }
+                }
+            },
+            None => {
+                return false;
+            }
+        }
+    }
+
+    // Metrics are keyed by fqdn, a renamed device keeps its collected state under the new name
+    pub fn rename_device(self: &mut IMDS, old_fqdn: &String, new_fqdn: &String) {
+        if self.metrics_storage.devices.contains_key(new_fqdn) {
+            // Replacing them would mix two devices' counters, the old entry expires on its own
+            log_warn!("renamed device already has metrics, keeping both", "old_fqdn" => old_fqdn, "fqdn" => new_fqdn);
+            return;
+        }
+        self.unknown_reports.remove(new_fqdn);
+        if let Some(mut device_metrics) = self.metrics_storage.devices.remove(old_fqdn) {
+            let fqdn_splitted : Vec<&str> = new_fqdn.split('.').collect();
+            device_metrics.fqdn = new_fqdn.clone();
+            device_metrics.hostname = fqdn_splitted[0].to_string();
+            self.metrics_storage.devices.insert(new_fqdn.clone(), device_metrics);
+        }
+    }
+
     pub fn refresh_device(self: &mut IMDS, device_fqdn: &String) {
         match self.metrics_storage.devices.get_mut(device_fqdn) {
             Some(device) => {
-                device.expiry = utilities::tools::get_time() + 60.0;
+                device.expiry = utilities::tools::get_time() + self.expiry;
                 return;
             },
             None => {}
@@ -97,37 +191,44 @@ impl IMDS {
         let fqdn_splitted : Vec<&str> = device_fqdn.split('.').collect();
         let hostname = fqdn_splitted[0];
         let dm = models::metrics::DeviceMetrics {
-            expiry: utilities::tools::get_time() + 60.0,
+            expiry: utilities::tools::get_time() + self.expiry,
             fqdn: device_fqdn.clone(),
             hostname: hostname.to_string(),
             up: None,
+            last_report: None,
             interfaces: HashMap::new(),
         };
         self.metrics_storage.devices.insert(device_fqdn.clone(), dm);
     }
 
-    pub fn report_device(self: &mut IMDS, connection: &db::Connection, dmr: models::json::DeviceMonitorReport) {
-        let device;
-        match self.metrics_storage.devices.get_mut(&dmr.fqdn) {
-            Some(value) => {
-                device = value;
-            },
-            None => {
-                // TODO: log? this means we got a report from a host that is not being monitored, it is possible this is normal on device removal
-                return;
-            }
+    pub fn report_device(self: &mut IMDS, dmr: models::json::DeviceMonitorReport) -> models::json::MonitorReportResult {
+        let topology = self.topology.clone();
+        let mut result = models::json::MonitorReportResult {
+            fqdn: dmr.fqdn.clone(),
+            accepted: false,
+            unknown_device: false,
+            unknown_if_indexes: Vec::new(),
+        };
+        if !self.metrics_storage.devices.contains_key(&dmr.fqdn) {
+            // A host that is not being monitored, this is normal for a while after device removal.
+            // The poller should resync its targets from /device/monitor.
+            self.unknown_report_counters(&dmr.fqdn).device_reports += 1;
+            log_debug!("device report for unmonitored device", "fqdn" => dmr.fqdn);
+            result.unknown_device = true;
+            return result;
         }
+        let device = self.metrics_storage.devices.get_mut(&dmr.fqdn).unwrap();
         
         if let Some(device_up) = device.up {
             if device_up != dmr.up {
-                if let Some(device) = models::dbo::Device::find_by_fqdn(connection, &dmr.fqdn) {
+                if let Some(device_id) = topology.device_id_by_fqdn(&dmr.fqdn) {
                     let mut neighbors: HashSet<String> = HashSet::new();
-                    for interface in device.interfaces(connection).iter() {
-                        if let Some(conn_iface) = interface.peer_interface(connection) {
-                            let conn_device = conn_iface.device(connection);
-                            let conn_fqdn = format!("{}.{}", conn_device.name, conn_device.dns_domain);
-                            if !neighbors.contains(&conn_fqdn) {
-                                neighbors.insert(conn_fqdn);
+                    for interface in topology.device_interfaces(device_id).iter() {
+                        if let Some(conn_iface) = topology.peer_interface(interface.id) {
+                            if let Some(conn_fqdn) = topology.fqdn(conn_iface.device_id) {
+                                if !neighbors.contains(&conn_fqdn) {
+                                    neighbors.insert(conn_fqdn);
+                                }
                             }
                         }
                     }
@@ -139,16 +240,19 @@ impl IMDS {
             }
         }
         device.up = Some(dmr.up);
+        device.last_report = Some(utilities::tools::get_time());
+        result.accepted = true;
+        return result;
     }
 
-    pub fn refresh_interface(self: &mut IMDS, device_fqdn: &String, if_index: i32, interface_type: &String, name: &String, neighbors: bool, speed_override: Option<i32>) {
+    pub fn refresh_interface(self: &mut IMDS, device_fqdn: &String, if_index: i32, interface_type: &String, name: &String, neighbors: bool, speed_override: Option<i32>, aggregate_index: Option<i32>) {
         let device;
         match self.metrics_storage.devices.get_mut(device_fqdn) {
             Some(value) => {
                 device = value;
             },
             None => {
-                // TODO: log? wtf?
+                log_warn!("interface refresh for device missing from IMDS", "fqdn" => device_fqdn, "if_index" => if_index);
                 return;
             }
         }
@@ -157,16 +261,18 @@ impl IMDS {
                 if target_interface.name != *name { target_interface.name = name.clone(); }
                 target_interface.neighbors = neighbors;
                 target_interface.speed_override = speed_override;
-                target_interface.expiry = utilities::tools::get_time() + 60.0;
+                target_interface.aggregate_index = aggregate_index;
+                target_interface.expiry = utilities::tools::get_time() + self.expiry;
                 return;
             },
             None => {}
         }
         device.interfaces.insert(if_index, models::metrics::InterfaceMetrics {
-            expiry: utilities::tools::get_time() + 60.0,
+            expiry: utilities::tools::get_time() + self.expiry,
             name: name.clone(),
             neighbors: neighbors,
             speed_override: speed_override,
+            aggregate_index: aggregate_index,
             interface_type: interface_type.clone(),
 
             in_octets: None,
@@ -182,31 +288,84 @@ impl IMDS {
             out_discards: None,
             up: None,
             speed: None,
+
+            in_octets_timestamp: None,
+            out_octets_timestamp: None,
+            in_bps: None,
+            out_bps: None,
         });
     }
 
-    pub fn report_interfaces(self: &mut IMDS, connection: &db::Connection, imr: models::json::InterfaceMonitorReport) {
-        let device;
-        match self.metrics_storage.devices.get_mut(&imr.device_fqdn) {
-            Some(value) => {
-                device = value;
-            },
-            None => {
-                // TODO: log? this means we got a report from a host that is not being monitored, it is possible this is normal on device removal
-                return;
+    pub fn report_interfaces(self: &mut IMDS, imr: models::json::InterfaceMonitorReport) -> models::json::MonitorReportResult {
+        let topology = self.topology.clone();
+        let mut result = models::json::MonitorReportResult {
+            fqdn: imr.device_fqdn.clone(),
+            accepted: false,
+            unknown_device: false,
+            unknown_if_indexes: Vec::new(),
+        };
+        if !self.metrics_storage.devices.contains_key(&imr.device_fqdn) {
+            // A host that is not being monitored, this is normal for a while after device removal.
+            // The poller should resync its targets from /device/monitor.
+            self.unknown_report_counters(&imr.device_fqdn).device_reports += 1;
+            log_debug!("interface report for unmonitored device", "fqdn" => imr.device_fqdn);
+            result.unknown_device = true;
+            return result;
+        }
+        for interface_report in imr.interfaces.iter() {
+            let device_known_if_index = match self.metrics_storage.devices.get(&imr.device_fqdn) {
+                Some(device) => device.interfaces.contains_key(&interface_report.if_index),
+                None => false,
+            };
+            if !device_known_if_index {
+                // An interface we don't really follow, e.g. it was removed by discovery
+                result.unknown_if_indexes.push(interface_report.if_index);
+            }
+        }
+        if !result.unknown_if_indexes.is_empty() {
+            self.unknown_report_counters(&imr.device_fqdn).interface_reports += result.unknown_if_indexes.len() as u64;
+            log_debug!("interface report for unknown ifIndexes", "fqdn" => imr.device_fqdn, "if_indexes" => format!("{:?}", result.unknown_if_indexes));
+        }
+        let device = self.metrics_storage.devices.get_mut(&imr.device_fqdn).unwrap();
+        // Link states as they are after the whole report, so that a bundle reported down together
+        // with its members is not taken for a degraded one, whatever the order of the report
+        let mut interfaces_shadow = device.interfaces.clone();
+        for interface_report in imr.interfaces.iter() {
+            if interface_report.up.is_none() { continue; }
+            if let Some(shadow_interface) = interfaces_shadow.get_mut(&interface_report.if_index) {
+                shadow_interface.up = interface_report.up;
             }
         }
         for interface_report in imr.interfaces.iter() {
             let mut interface;
-            let mut interfaces_shadow = device.interfaces.clone();
             match device.interfaces.get_mut(&interface_report.if_index) {
                 Some(target_interface) => { interface = target_interface; },
                 None => {
-                    // TODO: log? this means we got a report for interface we don't really follow
                     continue;
                 }
             }
 
+            // Pollers may report one direction at a time, each rate keeps its own sample time
+            let report_time = utilities::tools::get_time();
+            if interface_report.in_octets.is_some() {
+                if let Some(in_octets_timestamp) = interface.in_octets_timestamp {
+                    let elapsed = report_time - in_octets_timestamp;
+                    if elapsed > 0.0 {
+                        interface.in_bps = octet_rate(interface.in_octets, interface_report.in_octets, elapsed);
+                    }
+                }
+                interface.in_octets_timestamp = Some(report_time);
+            }
+            if interface_report.out_octets.is_some() {
+                if let Some(out_octets_timestamp) = interface.out_octets_timestamp {
+                    let elapsed = report_time - out_octets_timestamp;
+                    if elapsed > 0.0 {
+                        interface.out_bps = octet_rate(interface.out_octets, interface_report.out_octets, elapsed);
+                    }
+                }
+                interface.out_octets_timestamp = Some(report_time);
+            }
+
             // TODO: statechanges should be emitted for errors?
             if interface_report.in_octets.is_some() { interface.in_octets = interface_report.in_octets; }
             if interface_report.out_octets.is_some() { interface.out_octets = interface_report.out_octets; }
@@ -223,43 +382,48 @@ impl IMDS {
                 // TODO: fix this nested hellhole :)
                 if let Some(old_state) = interface.up {
                     if let Some(new_state) = interface_report.up {
-                        if old_state != new_state {
+                        // A member going down while its bundle stays up is a degradation, not an outage
+                        let mut degraded_aggregate : Option<(i32, String)> = None;
+                        if old_state != new_state && !new_state {
+                            if let Some(aggregate_index) = interface.aggregate_index {
+                                if let Some(aggregate_interface) = interfaces_shadow.get(&aggregate_index) {
+                                    if aggregate_interface.up == Some(true) {
+                                        degraded_aggregate = Some((aggregate_index, aggregate_interface.name.clone()));
+                                    }
+                                }
+                            }
+                        }
+                        if let Some((aggregate_index, aggregate_name)) = degraded_aggregate {
+                            let mut members_state : HashMap<String, String> = HashMap::new();
+                            for member_interface in interfaces_shadow.values() {
+                                if member_interface.aggregate_index != Some(aggregate_index) { continue; }
+                                members_state.insert(member_interface.name.clone(), link_state_text(member_interface.up));
+                            }
+                            if let Ok(ref mut msgbus) = self.msgbus.lock() {
+                                let event = models::events::Event::lag_degraded_event(&imr.device_fqdn, &aggregate_name, &interface.name, &members_state, old_state, new_state);
+                                msgbus.event(event);
+                            }
+                        } else if old_state != new_state {
                             let mut neighbor : Option<String> = None;
                             let mut neighbor_interface_name : Option<String> = None;
-                            let mut link_interfaces : Vec<models::dbo::Interface> = Vec::new();
+                            let mut link_interfaces : Vec<&models::dbo::Interface> = Vec::new();
                             let mut link_statuses : HashMap<String, String> = HashMap::new();
-                            if let Some(connpair) = ConnectionPair::load_by_fqdn_ifindex(connection, &imr.device_fqdn, &interface_report.if_index) {
+                            if let Some(connpair) = ConnectionPair::lookup_by_fqdn_ifindex(&topology, &imr.device_fqdn, &interface_report.if_index) {
                                 if let Some(remote_info) = connpair.remote_info {
-                                    neighbor = Some(format!("{}.{}", remote_info.device.name, remote_info.device.dns_domain));
-                                    neighbor_interface_name = Some(remote_info.interface.name());
-                                    for remote_peer_candidate in remote_info.device.interfaces(connection) {
-                                        if let Some(rpc_remote_interface) = remote_peer_candidate.peer_interface(connection) {
-                                            if rpc_remote_interface.device_id == connpair.local_device.id {
-                                                link_interfaces.push(rpc_remote_interface.clone());
+                                    neighbor = Some(remote_info.fqdn);
+                                    neighbor_interface_name = Some(remote_info.interface_name);
+                                    for remote_peer_candidate in topology.device_interfaces(remote_info.device_id).iter() {
+                                        if let Some(rpc_remote_interface) = topology.peer_interface(remote_peer_candidate.id) {
+                                            if rpc_remote_interface.device_id == connpair.local_device_id {
+                                                link_interfaces.push(rpc_remote_interface);
                                             }
                                         }
                                     }
                                 }
                             }
                             for link_interface in link_interfaces.iter() {
-                                if let Some(link_interface_data) = interfaces_shadow.get_mut(&link_interface.index) {
-                                    if link_interface.index == interface_report.if_index {
-                                        link_interface_data.up = interface_report.up;
-                                    }
-                                    let status : String;
-                                    match link_interface_data.up {
-                                        Some(value) => {
-                                            if value {
-                                                status = "up".to_string();
-                                            } else {
-                                                status = "down".to_string();
-                                            }
-                                        },
-                                        None => {
-                                            status = "unknown".to_string();
-                                        }
-                                    }
-                                    link_statuses.insert(link_interface_data.name.clone(), status);
+                                if let Some(link_interface_data) = interfaces_shadow.get(&link_interface.index) {
+                                    link_statuses.insert(link_interface_data.name.clone(), link_state_text(link_interface_data.up));
                                 }
                             }
                             if let Ok(ref mut msgbus) = self.msgbus.lock() {
@@ -277,10 +441,10 @@ impl IMDS {
                         if old_state != new_state {
                             let mut neighbor : Option<String> = None;
                             let mut neighbor_interface_name : Option<String> = None;
-                            if let Some(connpair) = ConnectionPair::load_by_fqdn_ifindex(connection, &imr.device_fqdn, &interface_report.if_index) {
+                            if let Some(connpair) = ConnectionPair::lookup_by_fqdn_ifindex(&topology, &imr.device_fqdn, &interface_report.if_index) {
                                 if let Some(remote_info) = connpair.remote_info {
-                                    neighbor = Some(format!("{}.{}", remote_info.device.name, remote_info.device.dns_domain));
-                                    neighbor_interface_name = Some(remote_info.interface.name());
+                                    neighbor = Some(remote_info.fqdn);
+                                    neighbor_interface_name = Some(remote_info.interface_name);
                                 }
                             }
                             if let Ok(ref mut msgbus) = self.msgbus.lock() {
@@ -293,6 +457,8 @@ impl IMDS {
                 interface.speed = interface_report.speed;
             }
         }
+        result.accepted = true;
+        return result;
     }
 
     pub fn get_metrics(self: &IMDS) -> Vec<models::metrics::LabeledMetric> {
@@ -414,6 +580,49 @@ impl IMDS {
         return metric_values;
     }
 
+    pub fn get_self_metrics(self: &IMDS) -> Vec<models::metrics::LabeledMetric> {
+        let jaspy_nexus_unknown_device_reports = "jaspy_nexus_unknown_device_reports".to_string();
+        let jaspy_nexus_unknown_interface_reports = "jaspy_nexus_unknown_interface_reports".to_string();
+
+        let mut metric_values: Vec<models::metrics::LabeledMetric> = Vec::new();
+        for (fqdn, unknown_report_counters) in self.unknown_reports.iter() {
+            let mut labels: HashMap<String,String> = HashMap::new();
+            labels.insert("fqdn".to_string(), fqdn.clone());
+            metric_values.push(models::metrics::LabeledMetric::new(
+                &jaspy_nexus_unknown_device_reports, models::metrics::MetricValue::Uint64(unknown_report_counters.device_reports),
+                &labels
+            ));
+            metric_values.push(models::metrics::LabeledMetric::new(
+                &jaspy_nexus_unknown_interface_reports, models::metrics::MetricValue::Uint64(unknown_report_counters.interface_reports),
+                &labels
+            ));
+        }
+
+        let no_labels: HashMap<String,String> = HashMap::new();
+        let mut interface_count: u64 = 0;
+        for (_device_key, device_metrics) in self.metrics_storage.devices.iter() {
+            interface_count += device_metrics.interfaces.len() as u64;
+        }
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_imds_devices".to_string(), models::metrics::MetricValue::Uint64(self.metrics_storage.devices.len() as u64),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_imds_interfaces".to_string(), models::metrics::MetricValue::Uint64(interface_count),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_imds_pruned_devices".to_string(), models::metrics::MetricValue::Uint64(self.pruned_devices),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_imds_pruned_interfaces".to_string(), models::metrics::MetricValue::Uint64(self.pruned_interfaces),
+            &no_labels
+        ));
+
+        return metric_values;
+    }
+
     pub fn get_fast_metrics(self: &IMDS) -> Vec<models::metrics::LabeledMetric> {
         let jaspy_device_up = "jaspy_device_up".to_string();
         let jaspy_interface_up = "jaspy_interface_up".to_string();
@@ -0,0 +1,184 @@
+use models;
+use std::collections::{HashMap, HashSet};
+
+pub const MATCHED_BY_NAME: &str = "name";
+pub const MATCHED_BY_INDEX: &str = "ifIndex";
+pub const MATCHED_BY_ALIAS: &str = "alias";
+
+// Short and long forms of the same port type, lowercase. Names differing only in these prefixes
+// refer to the same port, firmware upgrades tend to switch between them.
+const INTERFACE_NAME_ALIASES: [(&str, &str); 17] = [
+    ("gi", "gigabitethernet"),
+    ("ge", "gigabitethernet"),
+    ("te", "tengigabitethernet"),
+    ("tengige", "tengigabitethernet"),
+    ("fa", "fastethernet"),
+    ("et", "ethernet"),
+    ("eth", "ethernet"),
+    ("twe", "twentyfivegige"),
+    ("fo", "fortygigabitethernet"),
+    ("fortygige", "fortygigabitethernet"),
+    ("hu", "hundredgige"),
+    ("hundredgigabitethernet", "hundredgige"),
+    ("po", "port-channel"),
+    ("portchannel", "port-channel"),
+    ("vl", "vlan"),
+    ("lo", "loopback"),
+    ("mgmt", "management"),
+];
+
+// Returns None for names without a numeric part, those are too ambiguous to match loosely
+pub fn normalize_interface_name(name: &str) -> Option<String> {
+    let compact : String = name.chars().filter(|character| !character.is_whitespace()).collect::<String>().to_lowercase();
+    let split_at = match compact.find(|character: char| character.is_ascii_digit()) {
+        Some(split_at) if split_at > 0 => split_at,
+        _ => { return None; }
+    };
+    let (prefix, port) = compact.split_at(split_at);
+    let canonical_prefix = INTERFACE_NAME_ALIASES.iter()
+        .find(|&&(alias, _)| alias == prefix)
+        .map(|&(_, canonical)| canonical)
+        .unwrap_or(prefix);
+    return Some(format!("{}{}", canonical_prefix, port));
+}
+
+pub struct InterfaceMatch<'a> {
+    pub interface: &'a models::dbo::Interface,
+    pub matched_by: &'static str,
+}
+
+// Pairs every discovered interface with at most one stored interface: by name first, then by
+// ifIndex and type, then by normalized name. Loose matches are only made when they are unambiguous.
+pub fn match_interfaces<'a>(discovered_interfaces: &Vec<&models::json::DiscoveredInterface>, current_interfaces: &'a Vec<models::dbo::Interface>) -> Vec<Option<InterfaceMatch<'a>>> {
+    let mut matches : Vec<Option<InterfaceMatch<'a>>> = discovered_interfaces.iter().map(|_| None).collect();
+    let mut matched_ids : HashSet<i32> = HashSet::new();
+
+    for (position, discovered_interface) in discovered_interfaces.iter().enumerate() {
+        if let Some(current_interface) = current_interfaces.iter().find(|current_interface| current_interface.name == discovered_interface.name && !matched_ids.contains(&current_interface.id)) {
+            matched_ids.insert(current_interface.id);
+            matches[position] = Some(InterfaceMatch { interface: current_interface, matched_by: MATCHED_BY_NAME });
+        }
+    }
+
+    for (position, discovered_interface) in discovered_interfaces.iter().enumerate() {
+        if matches[position].is_some() { continue; }
+        let candidates : Vec<&models::dbo::Interface> = current_interfaces.iter()
+            .filter(|current_interface| !matched_ids.contains(&current_interface.id))
+            .filter(|current_interface| current_interface.index == discovered_interface.index && current_interface.interface_type == discovered_interface.interface_type)
+            .collect();
+        if candidates.len() == 1 {
+            matched_ids.insert(candidates[0].id);
+            matches[position] = Some(InterfaceMatch { interface: candidates[0], matched_by: MATCHED_BY_INDEX });
+        }
+    }
+
+    let mut discovered_by_alias : HashMap<String, Vec<usize>> = HashMap::new();
+    for (position, discovered_interface) in discovered_interfaces.iter().enumerate() {
+        if matches[position].is_some() { continue; }
+        if let Some(normalized_name) = normalize_interface_name(&discovered_interface.name) {
+            discovered_by_alias.entry(normalized_name).or_insert_with(Vec::new).push(position);
+        }
+    }
+    let mut current_by_alias : HashMap<String, Vec<&'a models::dbo::Interface>> = HashMap::new();
+    for current_interface in current_interfaces.iter() {
+        if matched_ids.contains(&current_interface.id) { continue; }
+        if let Some(normalized_name) = normalize_interface_name(&current_interface.name) {
+            current_by_alias.entry(normalized_name).or_insert_with(Vec::new).push(current_interface);
+        }
+    }
+    for (normalized_name, positions) in discovered_by_alias.iter() {
+        if positions.len() != 1 { continue; }
+        if let Some(candidates) = current_by_alias.get(normalized_name) {
+            if candidates.len() == 1 {
+                matches[positions[0]] = Some(InterfaceMatch { interface: candidates[0], matched_by: MATCHED_BY_ALIAS });
+            }
+        }
+    }
+    return matches;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn discovered(name: &str, index: i32, interface_type: &str) -> models::json::DiscoveredInterface {
+        return models::json::DiscoveredInterface {
+            index: index,
+            interface_type: interface_type.to_string(),
+            display_name: None,
+            name: name.to_string(),
+            alias: None,
+            description: None,
+            aggregate_index: None,
+        };
+    }
+
+    fn stored(id: i32, name: &str, index: i32, interface_type: &str) -> models::dbo::Interface {
+        return models::dbo::Interface {
+            id: id,
+            index: index,
+            interface_type: interface_type.to_string(),
+            connected_interface: None,
+            device_id: 1,
+            display_name: None,
+            name: name.to_string(),
+            alias: None,
+            description: None,
+            polling_enabled: None,
+            speed_override: None,
+            virtual_connection: None,
+            aggregate_interface: None,
+        };
+    }
+
+    fn matched(matches: &Vec<Option<InterfaceMatch>>) -> Vec<Option<(i32, &'static str)>> {
+        return matches.iter().map(|interface_match| interface_match.as_ref().map(|interface_match| (interface_match.interface.id, interface_match.matched_by))).collect();
+    }
+
+    #[test]
+    fn normalizes_abbreviated_port_types() {
+        assert_eq!(normalize_interface_name("Gi1/0/1"), Some("gigabitethernet1/0/1".to_string()));
+        assert_eq!(normalize_interface_name("GigabitEthernet1/0/1"), normalize_interface_name("Gi1/0/1"));
+        assert_eq!(normalize_interface_name("Po 10"), normalize_interface_name("Port-channel10"));
+        assert_eq!(normalize_interface_name("xe-0/0/1"), Some("xe-0/0/1".to_string()));
+        assert_eq!(normalize_interface_name("mgmt"), None);
+        assert_eq!(normalize_interface_name("1/1"), None);
+    }
+
+    #[test]
+    fn matches_by_name_first() {
+        let discovered_interfaces = vec![discovered("Gi1/0/1", 7, "ethernetCsmacd")];
+        let current_interfaces = vec![stored(1, "Gi1/0/2", 7, "ethernetCsmacd"), stored(2, "Gi1/0/1", 3, "ethernetCsmacd")];
+        let matches = match_interfaces(&discovered_interfaces.iter().collect::<Vec<_>>(), &current_interfaces);
+        assert_eq!(matched(&matches), vec![Some((2, MATCHED_BY_NAME))]);
+    }
+
+    #[test]
+    fn matches_by_index_only_with_the_same_type() {
+        let discovered_interfaces = vec![discovered("eth1", 5, "ethernetCsmacd"), discovered("vlan6", 6, "l2vlan")];
+        let current_interfaces = vec![stored(1, "eth0", 5, "ethernetCsmacd"), stored(2, "eth2", 6, "ethernetCsmacd")];
+        let matches = match_interfaces(&discovered_interfaces.iter().collect::<Vec<_>>(), &current_interfaces);
+        assert_eq!(matched(&matches), vec![Some((1, MATCHED_BY_INDEX)), None]);
+    }
+
+    #[test]
+    fn matches_renamed_port_type_by_alias() {
+        let discovered_interfaces = vec![discovered("Gi1/0/1", 101, "ethernetCsmacd")];
+        let current_interfaces = vec![stored(1, "GigabitEthernet1/0/1", 1, "ethernetCsmacd")];
+        let matches = match_interfaces(&discovered_interfaces.iter().collect::<Vec<_>>(), &current_interfaces);
+        assert_eq!(matched(&matches), vec![Some((1, MATCHED_BY_ALIAS))]);
+    }
+
+    #[test]
+    fn skips_ambiguous_alias_matches() {
+        let discovered_interfaces = vec![discovered("Gi1/0/1", 101, "ethernetCsmacd"), discovered("ge1/0/1", 102, "ethernetCsmacd")];
+        let current_interfaces = vec![stored(1, "GigabitEthernet1/0/1", 1, "ethernetCsmacd")];
+        let matches = match_interfaces(&discovered_interfaces.iter().collect::<Vec<_>>(), &current_interfaces);
+        assert_eq!(matched(&matches), vec![None, None]);
+
+        let discovered_interfaces = vec![discovered("GigabitEthernet1/0/1", 101, "ethernetCsmacd")];
+        let current_interfaces = vec![stored(1, "Gi1/0/1", 1, "ethernetCsmacd"), stored(2, "ge1/0/1", 2, "ethernetCsmacd")];
+        let matches = match_interfaces(&discovered_interfaces.iter().collect::<Vec<_>>(), &current_interfaces);
+        assert_eq!(matched(&matches), vec![None]);
+    }
+}
@@ -0,0 +1,592 @@
+use models;
+use serde_json;
+use serde_yaml;
+use csv;
+use std::collections::{HashMap, HashSet, BTreeMap};
+use diesel::pg::PgConnection;
+use rocket::http::ContentType;
+use utilities;
+use utilities::auth::AuthenticatedToken;
+
+pub const INVENTORY_VERSION: u32 = 1;
+
+pub const ENTITY_DEVICE: &str = "device";
+pub const ENTITY_INTERFACE: &str = "interface";
+pub const ENTITY_POSITION: &str = "position";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum InventoryFormat {
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl InventoryFormat {
+    pub fn parse(format: &Option<String>) -> Result<InventoryFormat, String> {
+        match format.as_ref().map(|format| format.to_lowercase()) {
+            None => Ok(InventoryFormat::Json),
+            Some(ref format) if format == "json" => Ok(InventoryFormat::Json),
+            Some(ref format) if format == "yaml" || format == "yml" => Ok(InventoryFormat::Yaml),
+            Some(ref format) if format == "csv" => Ok(InventoryFormat::Csv),
+            Some(format) => Err(format!("unknown inventory format {}, expected json, yaml or csv", format)),
+        }
+    }
+
+    pub fn content_type(self: &InventoryFormat) -> ContentType {
+        match *self {
+            InventoryFormat::Json => ContentType::JSON,
+            InventoryFormat::Yaml => ContentType::new("application", "x-yaml"),
+            InventoryFormat::Csv => ContentType::CSV,
+        }
+    }
+}
+
+// CSV has no nesting, so every interface is one row repeating its device columns. A device
+// without interfaces gets a single row with empty interface columns.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct InventoryCsvRow {
+    name: String,
+    dns_domain: String,
+    base_mac: Option<String>,
+    polling_enabled: Option<bool>,
+    os_info: Option<String>,
+    credential_profile: Option<String>,
+    position_x: Option<f64>,
+    position_y: Option<f64>,
+    position_super_node: Option<bool>,
+    position_expanded_by_default: Option<bool>,
+    interface_name: Option<String>,
+    interface_index: Option<i32>,
+    interface_type: Option<String>,
+    interface_display_name: Option<String>,
+    interface_alias: Option<String>,
+    interface_description: Option<String>,
+    interface_polling_enabled: Option<bool>,
+    interface_speed_override: Option<i32>,
+    virtual_connection_device: Option<String>,
+    virtual_connection_interface: Option<String>,
+}
+
+fn csv_rows(inventory: &models::json::Inventory) -> Vec<InventoryCsvRow> {
+    let mut rows : Vec<InventoryCsvRow> = Vec::new();
+    for inventory_device in inventory.devices.iter() {
+        let device_row = InventoryCsvRow {
+            name: inventory_device.name.clone(),
+            dns_domain: inventory_device.dns_domain.clone(),
+            base_mac: inventory_device.base_mac.clone(),
+            polling_enabled: inventory_device.polling_enabled,
+            os_info: inventory_device.os_info.clone(),
+            credential_profile: inventory_device.credential_profile.clone(),
+            position_x: inventory_device.position.as_ref().map(|position| position.x),
+            position_y: inventory_device.position.as_ref().map(|position| position.y),
+            position_super_node: inventory_device.position.as_ref().map(|position| position.super_node),
+            position_expanded_by_default: inventory_device.position.as_ref().map(|position| position.expanded_by_default),
+            ..Default::default()
+        };
+        if inventory_device.interfaces.is_empty() {
+            rows.push(device_row);
+            continue;
+        }
+        for inventory_interface in inventory_device.interfaces.iter() {
+            rows.push(InventoryCsvRow {
+                name: device_row.name.clone(),
+                dns_domain: device_row.dns_domain.clone(),
+                base_mac: device_row.base_mac.clone(),
+                polling_enabled: device_row.polling_enabled,
+                os_info: device_row.os_info.clone(),
+                credential_profile: device_row.credential_profile.clone(),
+                position_x: device_row.position_x,
+                position_y: device_row.position_y,
+                position_super_node: device_row.position_super_node,
+                position_expanded_by_default: device_row.position_expanded_by_default,
+                interface_name: Some(inventory_interface.name.clone()),
+                interface_index: Some(inventory_interface.index),
+                interface_type: Some(inventory_interface.interface_type.clone()),
+                interface_display_name: inventory_interface.display_name.clone(),
+                interface_alias: inventory_interface.alias.clone(),
+                interface_description: inventory_interface.description.clone(),
+                interface_polling_enabled: inventory_interface.polling_enabled,
+                interface_speed_override: inventory_interface.speed_override,
+                virtual_connection_device: inventory_interface.virtual_connection.as_ref().map(|peer| peer.device.clone()),
+                virtual_connection_interface: inventory_interface.virtual_connection.as_ref().map(|peer| peer.interface.clone()),
+            });
+        }
+    }
+    return rows;
+}
+
+fn from_csv_rows(rows: Vec<InventoryCsvRow>) -> Result<models::json::Inventory, String> {
+    let mut inventory_devices : Vec<models::json::InventoryDevice> = Vec::new();
+    let mut device_indexes : HashMap<(String, String), usize> = HashMap::new();
+    for (row_number, row) in rows.into_iter().enumerate() {
+        // Line 1 is the header
+        let line = row_number + 2;
+        let device_key = (row.name.clone(), row.dns_domain.clone());
+        let device_index = match device_indexes.get(&device_key).cloned() {
+            Some(device_index) => device_index,
+            None => {
+                let position = match (row.position_x, row.position_y) {
+                    (Some(x), Some(y)) => Some(models::json::InventoryPosition {
+                        x: x,
+                        y: y,
+                        super_node: row.position_super_node.unwrap_or(false),
+                        expanded_by_default: row.position_expanded_by_default.unwrap_or(false),
+                    }),
+                    (None, None) => None,
+                    _ => { return Err(format!("line {}: positionX and positionY must be given together", line)); }
+                };
+                inventory_devices.push(models::json::InventoryDevice {
+                    name: row.name.clone(),
+                    dns_domain: row.dns_domain.clone(),
+                    base_mac: row.base_mac.clone(),
+                    polling_enabled: row.polling_enabled,
+                    os_info: row.os_info.clone(),
+                    credential_profile: row.credential_profile.clone(),
+                    position: position,
+                    interfaces: Vec::new(),
+                });
+                device_indexes.insert(device_key, inventory_devices.len() - 1);
+                inventory_devices.len() - 1
+            }
+        };
+        let interface_name = match row.interface_name {
+            Some(interface_name) => interface_name,
+            None => { continue; }
+        };
+        let (interface_index, interface_type) = match (row.interface_index, row.interface_type) {
+            (Some(interface_index), Some(interface_type)) => (interface_index, interface_type),
+            _ => { return Err(format!("line {}: interface {} needs interfaceIndex and interfaceType", line, interface_name)); }
+        };
+        let virtual_connection = match (row.virtual_connection_device, row.virtual_connection_interface) {
+            (Some(device), Some(interface)) => Some(models::json::InventoryInterfaceRef { device: device, interface: interface }),
+            (None, None) => None,
+            _ => { return Err(format!("line {}: virtualConnectionDevice and virtualConnectionInterface must be given together", line)); }
+        };
+        inventory_devices[device_index].interfaces.push(models::json::InventoryInterface {
+            name: interface_name,
+            index: interface_index,
+            interface_type: interface_type,
+            display_name: row.interface_display_name,
+            alias: row.interface_alias,
+            description: row.interface_description,
+            polling_enabled: row.interface_polling_enabled,
+            speed_override: row.interface_speed_override,
+            virtual_connection: virtual_connection,
+        });
+    }
+    return Ok(models::json::Inventory {
+        version: INVENTORY_VERSION,
+        devices: inventory_devices,
+    });
+}
+
+pub fn serialize(inventory: &models::json::Inventory, format: InventoryFormat) -> Result<String, String> {
+    match format {
+        InventoryFormat::Json => {
+            return serde_json::to_string_pretty(inventory).map_err(|error| format!("{}", error));
+        },
+        InventoryFormat::Yaml => {
+            return serde_yaml::to_string(inventory).map_err(|error| format!("{}", error));
+        },
+        InventoryFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for row in csv_rows(inventory).iter() {
+                writer.serialize(row).map_err(|error| format!("{}", error))?;
+            }
+            let bytes = writer.into_inner().map_err(|error| format!("{}", error))?;
+            return String::from_utf8(bytes).map_err(|error| format!("{}", error));
+        }
+    }
+}
+
+pub fn deserialize(body: &str, format: InventoryFormat) -> Result<models::json::Inventory, String> {
+    match format {
+        InventoryFormat::Json => {
+            return serde_json::from_str(body).map_err(|error| format!("{}", error));
+        },
+        InventoryFormat::Yaml => {
+            return serde_yaml::from_str(body).map_err(|error| format!("{}", error));
+        },
+        InventoryFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(body.as_bytes());
+            let mut rows : Vec<InventoryCsvRow> = Vec::new();
+            for row in reader.deserialize() {
+                rows.push(row.map_err(|error| format!("{}", error))?);
+            }
+            return from_csv_rows(rows);
+        }
+    }
+}
+
+pub fn export(connection: &PgConnection) -> models::json::Inventory {
+    let mut devices = models::dbo::Device::all(connection);
+    devices.sort_by(|a, b| (&a.dns_domain, &a.name).cmp(&(&b.dns_domain, &b.name)));
+    let grouped_interfaces = models::dbo::Device::interfaces_grouped(&devices, connection);
+
+    let mut credential_profile_names : HashMap<i32, String> = HashMap::new();
+    for credential_profile in models::dbo::CredentialProfile::all(connection).into_iter() {
+        credential_profile_names.insert(credential_profile.id, credential_profile.name);
+    }
+    let mut interface_refs : HashMap<i32, models::json::InventoryInterfaceRef> = HashMap::new();
+    for (device, device_interfaces) in devices.iter().zip(grouped_interfaces.iter()) {
+        for interface in device_interfaces.iter() {
+            interface_refs.insert(interface.id, models::json::InventoryInterfaceRef {
+                device: format!("{}.{}", device.name, device.dns_domain),
+                interface: interface.name.clone(),
+            });
+        }
+    }
+
+    let mut inventory_devices : Vec<models::json::InventoryDevice> = Vec::new();
+    for (device, device_interfaces) in devices.iter().zip(grouped_interfaces.into_iter()) {
+        let mut device_interfaces = device_interfaces;
+        device_interfaces.sort_by_key(|interface| interface.index);
+        let mut inventory_interfaces : Vec<models::json::InventoryInterface> = Vec::new();
+        for interface in device_interfaces.iter() {
+            inventory_interfaces.push(models::json::InventoryInterface {
+                name: interface.name.clone(),
+                index: interface.index,
+                interface_type: interface.interface_type.clone(),
+                display_name: interface.display_name.clone(),
+                alias: interface.alias.clone(),
+                description: interface.description.clone(),
+                polling_enabled: interface.polling_enabled,
+                speed_override: interface.speed_override,
+                virtual_connection: interface.virtual_connection.and_then(|peer_id| interface_refs.get(&peer_id).cloned()),
+            });
+        }
+        inventory_devices.push(models::json::InventoryDevice {
+            name: device.name.clone(),
+            dns_domain: device.dns_domain.clone(),
+            base_mac: device.base_mac.clone(),
+            polling_enabled: device.polling_enabled,
+            os_info: device.os_info.clone(),
+            credential_profile: device.credential_profile_id.and_then(|credential_profile_id| credential_profile_names.get(&credential_profile_id).cloned()),
+            position: device.weathermap_info(connection).map(|weathermap_info| models::json::InventoryPosition {
+                x: weathermap_info.x,
+                y: weathermap_info.y,
+                super_node: weathermap_info.super_node,
+                expanded_by_default: weathermap_info.expanded_by_default,
+            }),
+            interfaces: inventory_interfaces,
+        });
+    }
+    return models::json::Inventory {
+        version: INVENTORY_VERSION,
+        devices: inventory_devices,
+    };
+}
+
+pub fn validate(inventory: &models::json::Inventory) -> Result<(), String> {
+    if inventory.version != INVENTORY_VERSION {
+        return Err(format!("unsupported inventory version {}, expected {}", inventory.version, INVENTORY_VERSION));
+    }
+    let mut device_fqdns : HashSet<String> = HashSet::new();
+    for inventory_device in inventory.devices.iter() {
+        if inventory_device.name.is_empty() || inventory_device.dns_domain.is_empty() {
+            return Err("every device needs a name and a dnsDomain".to_string());
+        }
+        let device_fqdn = format!("{}.{}", inventory_device.name, inventory_device.dns_domain);
+        if !device_fqdns.insert(device_fqdn.clone()) {
+            return Err(format!("device {} is listed more than once", device_fqdn));
+        }
+        let mut interface_names : HashSet<&String> = HashSet::new();
+        for inventory_interface in inventory_device.interfaces.iter() {
+            if !interface_names.insert(&inventory_interface.name) {
+                return Err(format!("interface {} of device {} is listed more than once", inventory_interface.name, device_fqdn));
+            }
+        }
+    }
+    return Ok(());
+}
+
+pub struct ImportOutcome {
+    pub changes: Vec<models::json::InventoryChange>,
+    // Published by the caller once the transaction has committed
+    pub events: Vec<models::events::Event>,
+    pub changed_devices: HashSet<String>,
+}
+
+fn push_change(changes: &mut Vec<models::json::InventoryChange>, entity: &str, key: &String, before: Option<&serde_json::Value>, after: &serde_json::Value) {
+    let action = if before.is_some() { utilities::audit::ACTION_UPDATE } else { utilities::audit::ACTION_CREATE };
+    let fields = utilities::audit::field_changes(before.unwrap_or(&serde_json::Value::Null), after);
+    if before.is_some() && fields.is_empty() { return; }
+    changes.push(models::json::InventoryChange {
+        entity: entity.to_string(),
+        key: key.clone(),
+        action: action.to_string(),
+        fields: fields,
+    });
+}
+
+fn resolve_interface_ref(interface_ref: &models::json::InventoryInterfaceRef, connection: &PgConnection) -> Result<models::dbo::Interface, utilities::errors::ApiError> {
+    match models::dbo::Device::find_by_fqdn(connection, &interface_ref.device) {
+        Some(device) => {
+            match device.interface_by_name(connection, &interface_ref.interface) {
+                Some(interface) => {
+                    return Ok(interface);
+                },
+                None => {
+                    return Err(utilities::errors::ApiError::BadRequest(format!("virtual connection peer {} {} not found", interface_ref.device, interface_ref.interface)));
+                }
+            }
+        },
+        None => {
+            return Err(utilities::errors::ApiError::BadRequest(format!("virtual connection peer device {} not found", interface_ref.device)));
+        }
+    }
+}
+
+// Creates or updates every device and interface in the inventory, nothing is deleted. Fields
+// missing from an entry are cleared like with PUT /device, a missing position is left alone.
+// Expects to run inside a transaction and a validated inventory.
+pub fn apply(inventory: &models::json::Inventory, token: &AuthenticatedToken, connection: &PgConnection) -> Result<ImportOutcome, utilities::errors::ApiError> {
+    let mut outcome = ImportOutcome {
+        changes: Vec::new(),
+        events: Vec::new(),
+        changed_devices: HashSet::new(),
+    };
+    let mut devices_by_fqdn : HashMap<String, models::dbo::Device> = HashMap::new();
+    let mut interfaces_before : HashMap<String, Option<serde_json::Value>> = HashMap::new();
+
+    // Devices and interfaces first, virtual connections may point at interfaces created later on
+    for inventory_device in inventory.devices.iter() {
+        let device_fqdn = format!("{}.{}", inventory_device.name, inventory_device.dns_domain);
+        let credential_profile_id : Option<i32> = match inventory_device.credential_profile {
+            Some(ref credential_profile) => Some(utilities::credentials::credential_profile_id(credential_profile, connection)?),
+            None => None,
+        };
+        let device : models::dbo::Device;
+        match models::dbo::Device::find_by_hostname_and_domain_name(connection, &inventory_device.name, &inventory_device.dns_domain) {
+            Some(mut existing_device) => {
+                interfaces_before.insert(device_fqdn.clone(), Some(utilities::audit::interfaces_state(&existing_device.interfaces(connection))));
+                let device_before = json!(existing_device);
+                if existing_device.polling_enabled != inventory_device.polling_enabled {
+                    outcome.events.push(models::events::Event::device_polling_changed_event(&device_fqdn, existing_device.polling_enabled, inventory_device.polling_enabled));
+                    existing_device.polling_enabled = inventory_device.polling_enabled;
+                }
+                if existing_device.os_info != inventory_device.os_info {
+                    outcome.events.push(models::events::Event::device_os_info_changed_event(&device_fqdn, &existing_device.os_info, &inventory_device.os_info));
+                    existing_device.os_info = inventory_device.os_info.clone();
+                }
+                if existing_device.base_mac != inventory_device.base_mac {
+                    outcome.events.push(models::events::Event::device_base_mac_changed_event(&device_fqdn, &existing_device.base_mac, &inventory_device.base_mac));
+                    existing_device.base_mac = inventory_device.base_mac.clone();
+                }
+                existing_device.credential_profile_id = credential_profile_id;
+                let device_after = json!(existing_device);
+                if device_after != device_before {
+                    existing_device.update(connection)?;
+                    outcome.changed_devices.insert(device_fqdn.clone());
+                    utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE, &device_fqdn, Some(device_before.clone()), Some(device_after.clone()));
+                }
+                push_change(&mut outcome.changes, ENTITY_DEVICE, &device_fqdn, Some(&device_before), &device_after);
+                device = existing_device;
+            },
+            None => {
+                interfaces_before.insert(device_fqdn.clone(), None);
+                let new_device = models::dbo::NewDevice {
+                    name: inventory_device.name.clone(),
+                    dns_domain: inventory_device.dns_domain.clone(),
+                    base_mac: inventory_device.base_mac.clone(),
+                    polling_enabled: inventory_device.polling_enabled,
+                    os_info: inventory_device.os_info.clone(),
+                    credential_profile_id: credential_profile_id,
+                };
+                device = models::dbo::Device::create(&new_device, connection)?;
+                let device_after = json!(device);
+                outcome.events.push(models::events::Event::device_created_event(&device_fqdn));
+                outcome.changed_devices.insert(device_fqdn.clone());
+                utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE, &device_fqdn, None, Some(device_after.clone()));
+                push_change(&mut outcome.changes, ENTITY_DEVICE, &device_fqdn, None, &device_after);
+            }
+        }
+
+        let current_interfaces = device.interfaces(connection);
+        for inventory_interface in inventory_device.interfaces.iter() {
+            let interface_key = format!("{} {}", device_fqdn, inventory_interface.name);
+            let (mut interface, interface_before) = match current_interfaces.iter().find(|interface| interface.name == inventory_interface.name) {
+                Some(current_interface) => (current_interface.clone(), Some(json!(current_interface))),
+                None => {
+                    let new_interface = models::dbo::NewInterface {
+                        index: inventory_interface.index,
+                        interface_type: inventory_interface.interface_type.clone(),
+                        device_id: device.id,
+                        name: inventory_interface.name.clone(),
+                        alias: inventory_interface.alias.clone(),
+                        description: inventory_interface.description.clone(),
+                    };
+                    (models::dbo::Interface::create(&new_interface, connection)?, None)
+                }
+            };
+            interface.index = inventory_interface.index;
+            interface.interface_type = inventory_interface.interface_type.clone();
+            interface.display_name = inventory_interface.display_name.clone();
+            interface.alias = inventory_interface.alias.clone();
+            interface.description = inventory_interface.description.clone();
+            interface.polling_enabled = inventory_interface.polling_enabled;
+            interface.speed_override = inventory_interface.speed_override;
+            let interface_after = json!(interface);
+            if interface_before.as_ref() != Some(&interface_after) {
+                interface.update(connection)?;
+                outcome.changed_devices.insert(device_fqdn.clone());
+            }
+            push_change(&mut outcome.changes, ENTITY_INTERFACE, &interface_key, interface_before.as_ref(), &interface_after);
+        }
+        devices_by_fqdn.insert(device_fqdn, device);
+    }
+
+    for inventory_device in inventory.devices.iter() {
+        let device_fqdn = format!("{}.{}", inventory_device.name, inventory_device.dns_domain);
+        let device = match devices_by_fqdn.get(&device_fqdn) {
+            Some(device) => device,
+            None => { continue; }
+        };
+        for inventory_interface in inventory_device.interfaces.iter() {
+            let virtual_connection : Option<i32> = match inventory_interface.virtual_connection {
+                Some(ref interface_ref) => Some(resolve_interface_ref(interface_ref, connection)?.id),
+                None => None,
+            };
+            if let Some(mut interface) = device.interface_by_name(connection, &inventory_interface.name) {
+                if interface.virtual_connection == virtual_connection { continue; }
+                let interface_key = format!("{} {}", device_fqdn, inventory_interface.name);
+                let mut fields : BTreeMap<String, models::json::FieldChange> = BTreeMap::new();
+                fields.insert("virtualConnection".to_string(), models::json::FieldChange {
+                    before: json!(interface.virtual_connection.and_then(|peer_id| models::dbo::Interface::by_id(peer_id, connection)).map(|peer| {
+                        let peer_device = peer.device(connection);
+                        format!("{}.{} {}", peer_device.name, peer_device.dns_domain, peer.name)
+                    })),
+                    after: json!(inventory_interface.virtual_connection.as_ref().map(|interface_ref| format!("{} {}", interface_ref.device, interface_ref.interface))),
+                });
+                interface.virtual_connection = virtual_connection;
+                interface.update(connection)?;
+                outcome.changed_devices.insert(device_fqdn.clone());
+                outcome.changes.push(models::json::InventoryChange {
+                    entity: ENTITY_INTERFACE.to_string(),
+                    key: interface_key,
+                    action: utilities::audit::ACTION_UPDATE.to_string(),
+                    fields: fields,
+                });
+            }
+        }
+
+        if let Some(ref position) = inventory_device.position {
+            match models::dbo::WeathermapDeviceInfo::lookup_by_device(connection, device) {
+                Some(mut weathermap_info) => {
+                    let position_before = json!(weathermap_info);
+                    weathermap_info.x = position.x;
+                    weathermap_info.y = position.y;
+                    weathermap_info.super_node = position.super_node;
+                    weathermap_info.expanded_by_default = position.expanded_by_default;
+                    let position_after = json!(weathermap_info);
+                    if position_after != position_before {
+                        weathermap_info.update(connection)?;
+                        utilities::audit::record(connection, token, utilities::audit::ENTITY_WEATHERMAP_POSITION, &device_fqdn, Some(position_before.clone()), Some(position_after.clone()));
+                    }
+                    push_change(&mut outcome.changes, ENTITY_POSITION, &device_fqdn, Some(&position_before), &position_after);
+                },
+                None => {
+                    let new_weathermap_info = models::dbo::NewWeathermapDeviceInfo {
+                        x: position.x,
+                        y: position.y,
+                        super_node: position.super_node,
+                        expanded_by_default: position.expanded_by_default,
+                        device_id: device.id,
+                    };
+                    let weathermap_info = models::dbo::WeathermapDeviceInfo::create(&new_weathermap_info, connection)?;
+                    let position_after = json!(weathermap_info);
+                    utilities::audit::record(connection, token, utilities::audit::ENTITY_WEATHERMAP_POSITION, &device_fqdn, None, Some(position_after.clone()));
+                    push_change(&mut outcome.changes, ENTITY_POSITION, &device_fqdn, None, &position_after);
+                }
+            }
+        }
+
+        let interfaces_after = utilities::audit::interfaces_state(&device.interfaces(connection));
+        utilities::audit::record(connection, token, utilities::audit::ENTITY_DEVICE_INTERFACES, &device_fqdn, interfaces_before.remove(&device_fqdn).unwrap_or(None), Some(interfaces_after));
+    }
+    return Ok(outcome);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inventory() -> models::json::Inventory {
+        return models::json::Inventory {
+            version: INVENTORY_VERSION,
+            devices: vec![
+                models::json::InventoryDevice {
+                    name: "core1".to_string(),
+                    dns_domain: "example.net".to_string(),
+                    base_mac: Some("00:11:22:33:44:55".to_string()),
+                    polling_enabled: Some(true),
+                    os_info: Some("IOS-XE 17.9, \"gibraltar\"".to_string()),
+                    credential_profile: Some("snmpv3-core".to_string()),
+                    position: Some(models::json::InventoryPosition { x: 12.5, y: -3.0, super_node: true, expanded_by_default: false }),
+                    interfaces: vec![
+                        models::json::InventoryInterface {
+                            name: "GigabitEthernet1/0/1".to_string(),
+                            index: 1,
+                            interface_type: "ethernetCsmacd".to_string(),
+                            display_name: Some("uplink".to_string()),
+                            alias: Some("to access1".to_string()),
+                            description: None,
+                            polling_enabled: Some(false),
+                            speed_override: Some(1000),
+                            virtual_connection: Some(models::json::InventoryInterfaceRef { device: "access1.example.net".to_string(), interface: "Gi0/1".to_string() }),
+                        },
+                        models::json::InventoryInterface {
+                            name: "Vlan10".to_string(),
+                            index: 10,
+                            interface_type: "propVirtual".to_string(),
+                            display_name: None,
+                            alias: None,
+                            description: Some("servers".to_string()),
+                            polling_enabled: None,
+                            speed_override: None,
+                            virtual_connection: None,
+                        },
+                    ],
+                },
+                models::json::InventoryDevice {
+                    name: "access1".to_string(),
+                    dns_domain: "example.net".to_string(),
+                    base_mac: None,
+                    polling_enabled: None,
+                    os_info: None,
+                    credential_profile: None,
+                    position: None,
+                    interfaces: Vec::new(),
+                },
+            ],
+        };
+    }
+
+    #[test]
+    fn csv_export_round_trips() {
+        let exported = inventory();
+        let body = serialize(&exported, InventoryFormat::Csv).unwrap();
+        let imported = deserialize(&body, InventoryFormat::Csv).unwrap();
+        assert_eq!(serde_json::to_value(&imported).unwrap(), serde_json::to_value(&exported).unwrap());
+    }
+
+    #[test]
+    fn csv_rows_round_trip() {
+        let exported = inventory();
+        let rows = csv_rows(&exported);
+        assert_eq!(rows.len(), 3);
+        let imported = from_csv_rows(rows).unwrap();
+        assert_eq!(serde_json::to_value(&imported).unwrap(), serde_json::to_value(&exported).unwrap());
+    }
+
+    #[test]
+    fn csv_rows_need_complete_pairs() {
+        let mut rows = csv_rows(&inventory());
+        rows[0].position_y = None;
+        assert!(from_csv_rows(rows).is_err());
+        let mut rows = csv_rows(&inventory());
+        rows[1].virtual_connection_interface = Some("Gi0/2".to_string());
+        assert!(from_csv_rows(rows).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use rocket::{Request, Data, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use utilities;
+use utilities::tools;
+extern crate serde_json;
+extern crate time;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl LogLevel {
+    pub fn parse(level: &str) -> Option<LogLevel> {
+        match level.to_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self: &LogLevel) -> &'static str {
+        match *self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+}
+
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(LogLevel::Info as usize);
+static LOG_JSON: AtomicBool = AtomicBool::new(false);
+
+// Called once from main before any thread is spawned, the config has already been validated
+pub fn init(logging_config: &utilities::config::LoggingConfig) {
+    if let Some(level) = LogLevel::parse(&logging_config.level) {
+        set_level(level);
+    }
+    set_json(logging_config.format.to_lowercase() == "json");
+}
+
+pub fn set_level(level: LogLevel) {
+    LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn set_json(json: bool) {
+    LOG_JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    return (level as usize) <= LOG_LEVEL.load(Ordering::Relaxed);
+}
+
+fn quote_text_value(value: &String) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        return format!("{:?}", value);
+    }
+    return value.clone();
+}
+
+pub fn write(level: LogLevel, module: &str, message: &str, fields: &Vec<(&str, String)>) {
+    let timestamp = format!("{}", time::now_utc().rfc3339());
+    let line : String;
+    if LOG_JSON.load(Ordering::Relaxed) {
+        let mut entry = serde_json::Map::new();
+        entry.insert("timestamp".to_string(), json!(timestamp));
+        entry.insert("level".to_string(), json!(level.as_str()));
+        entry.insert("module".to_string(), json!(module));
+        entry.insert("message".to_string(), json!(message));
+        for &(key, ref value) in fields.iter() {
+            entry.insert(key.to_string(), json!(value));
+        }
+        line = format!("{}", serde_json::Value::Object(entry));
+    } else {
+        let mut text = format!("{} {:5} {}: {}", timestamp, level.as_str(), module, message);
+        for &(key, ref value) in fields.iter() {
+            text.push_str(&format!(" {}={}", key, quote_text_value(value)));
+        }
+        line = text;
+    }
+    let stderr = ::std::io::stderr();
+    let mut handle = stderr.lock();
+    let _ = writeln!(handle, "{}", line);
+}
+
+#[macro_export]
+macro_rules! log_event {
+    ($level:expr, $message:expr $(, $key:expr => $value:expr)* $(,)*) => {
+        if $crate::utilities::logging::enabled($level) {
+            #[allow(unused_mut)]
+            let mut fields : Vec<(&str, String)> = Vec::new();
+            $( fields.push(($key, format!("{}", $value))); )*
+            $crate::utilities::logging::write($level, module_path!(), &$message, &fields);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { log_event!($crate::utilities::logging::LogLevel::Error, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { log_event!($crate::utilities::logging::LogLevel::Warn, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { log_event!($crate::utilities::logging::LogLevel::Info, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { log_event!($crate::utilities::logging::LogLevel::Debug, $($arg)*) };
+}
+
+struct RequestLogStartTime(f64);
+
+pub struct RequestLoggingFairing;
+
+impl Fairing for RequestLoggingFairing {
+    fn info(&self) -> Info {
+        return Info {
+            name: "Request logging",
+            kind: Kind::Request | Kind::Response,
+        };
+    }
+
+    fn on_request(&self, request: &mut Request, _data: &Data) {
+        request.local_cache(|| RequestLogStartTime(tools::get_time()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let started = request.local_cache(|| RequestLogStartTime(tools::get_time())).0;
+        let route = match request.route() {
+            Some(route) => route.uri.path().to_string(),
+            None => "unmatched".to_string(),
+        };
+        let status = response.status().code;
+        // Pollers report every few seconds, so only failures are logged above debug level
+        let level = if status >= 500 { LogLevel::Warn } else { LogLevel::Debug };
+        log_event!(level, "request handled",
+            "method" => request.method(),
+            "route" => route,
+            "uri" => request.uri(),
+            "status" => status,
+            "duration" => format!("{:.6}", tools::get_time() - started),
+        );
+    }
+}
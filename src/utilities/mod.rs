@@ -1,4 +1,17 @@
+#[macro_use]
+pub mod logging;
 pub mod imds;
 pub mod msgbus;
 pub mod tools;
-pub mod cache;
\ No newline at end of file
+pub mod cache;
+pub mod topology;
+pub mod selfmetrics;
+pub mod errors;
+pub mod config;
+pub mod auth;
+pub mod credentials;
+pub mod audit;
+pub mod pagination;
+pub mod inventory;
+pub mod backup;
+pub mod interfacematch;
\ No newline at end of file
@@ -1,24 +1,15 @@
 use models;
-use std::env;
+use std::collections::HashMap;
 extern crate serde_json;
 extern crate zmq;
 
 pub struct MessageBus {
     zmq_socket: zmq::Socket,
+    events_published: HashMap<String, u64>,
 }
 
 impl MessageBus {
-    pub fn new() -> MessageBus {
-        let env_opt = env::var("EVENT_PUBLISH");
-        let event_publish;
-        match env_opt {
-            Ok(env_opt) => {
-                event_publish = env_opt.clone();
-            },
-            Err(_) => {
-                panic!("EVENT_PUBLISH env var not set!");
-            }
-        }
+    pub fn new(event_publish: String) -> MessageBus {
 
         let zmq_context = zmq::Context::new();
         let zmq_socket;
@@ -38,14 +29,43 @@ impl MessageBus {
 
         return MessageBus {
             zmq_socket: zmq_socket,
+            events_published: HashMap::new(),
         };
     }
 
 
-    pub fn event(self: &MessageBus, event: models::events::Event) {
+    pub fn event(self: &mut MessageBus, event: models::events::Event) {
         let json_data = format!("{}", json!(event));
         if let Ok(_) = self.zmq_socket.send_str(&event.event_type.to_uppercase(), zmq::SNDMORE) {
-            if let Ok(_) = self.zmq_socket.send_str(&json_data, 0) {}
+            if let Ok(_) = self.zmq_socket.send_str(&json_data, 0) {
+                *self.events_published.entry(event.event_type.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn is_bound(self: &MessageBus) -> bool {
+        match self.zmq_socket.get_last_endpoint() {
+            Ok(Ok(endpoint)) => {
+                return !endpoint.is_empty();
+            },
+            _ => {
+                return false;
+            }
+        }
+    }
+
+    pub fn get_self_metrics(self: &MessageBus) -> Vec<models::metrics::LabeledMetric> {
+        let jaspy_nexus_events_published = "jaspy_nexus_events_published".to_string();
+
+        let mut metric_values: Vec<models::metrics::LabeledMetric> = Vec::new();
+        for (event_type, published) in self.events_published.iter() {
+            let mut labels: HashMap<String,String> = HashMap::new();
+            labels.insert("event_type".to_string(), event_type.clone());
+            metric_values.push(models::metrics::LabeledMetric::new(
+                &jaspy_nexus_events_published, models::metrics::MetricValue::Uint64(*published),
+                &labels
+            ));
         }
+        return metric_values;
     }
 }
\ No newline at end of file
@@ -0,0 +1,50 @@
+use models;
+use serde::Serialize;
+use rocket::Request;
+use rocket::response::{self, Responder};
+use rocket_contrib::json;
+use utilities;
+
+pub const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+// Body stays a plain array so existing clients keep working, the total goes into a header
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+}
+
+impl<'r, T: Serialize> Responder<'r> for Page<T> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let mut response = json::Json(self.items).respond_to(request)?;
+        response.set_raw_header(TOTAL_COUNT_HEADER, self.total.to_string());
+        return Ok(response);
+    }
+}
+
+pub fn pagination(sort: Option<String>, order: Option<String>, limit: Option<i64>, offset: Option<i64>, sort_fields: &[&str]) -> Result<models::dbo::Pagination, utilities::errors::ApiError> {
+    let sort = sort.unwrap_or("id".to_string());
+    if !sort_fields.contains(&sort.as_str()) {
+        return Err(utilities::errors::ApiError::BadRequest(format!("unknown sort field {}, expected one of {}", sort, sort_fields.join(", "))));
+    }
+    let descending = match order {
+        Some(ref order) if order == "desc" => true,
+        Some(ref order) if order == "asc" => false,
+        Some(order) => { return Err(utilities::errors::ApiError::BadRequest(format!("order must be asc or desc, not {}", order))); },
+        None => false,
+    };
+    if let Some(limit) = limit {
+        if limit < 0 {
+            return Err(utilities::errors::ApiError::BadRequest("limit must not be negative".to_string()));
+        }
+    }
+    let offset = offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(utilities::errors::ApiError::BadRequest("offset must not be negative".to_string()));
+    }
+    return Ok(models::dbo::Pagination {
+        sort: sort,
+        descending: descending,
+        limit: limit,
+        offset: offset,
+    });
+}
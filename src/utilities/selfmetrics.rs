@@ -0,0 +1,144 @@
+use models;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use rocket::{Request, Data, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use utilities::tools;
+
+pub struct RouteRequestCounters {
+    pub requests: u64,
+    pub latency_seconds_sum: f64,
+}
+
+pub struct SelfMetrics {
+    pub route_requests: HashMap<(String, String, u16), RouteRequestCounters>,
+    pub imds_lock_waits: u64,
+    pub imds_lock_wait_seconds_sum: f64,
+    pub worker_iterations: u64,
+    pub worker_duration_seconds_sum: f64,
+    pub worker_last_duration_seconds: f64,
+    pub weathermap_cache_hits: u64,
+    pub weathermap_cache_misses: u64,
+}
+
+impl SelfMetrics {
+    pub fn new() -> SelfMetrics {
+        return SelfMetrics {
+            route_requests: HashMap::new(),
+            imds_lock_waits: 0,
+            imds_lock_wait_seconds_sum: 0.0,
+            worker_iterations: 0,
+            worker_duration_seconds_sum: 0.0,
+            worker_last_duration_seconds: 0.0,
+            weathermap_cache_hits: 0,
+            weathermap_cache_misses: 0,
+        };
+    }
+
+    pub fn record_request(self: &mut SelfMetrics, method: &str, route: &str, status: u16, latency: f64) {
+        let route_request_counters = self.route_requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert(RouteRequestCounters { requests: 0, latency_seconds_sum: 0.0 });
+        route_request_counters.requests += 1;
+        route_request_counters.latency_seconds_sum += latency;
+    }
+
+    pub fn record_imds_lock_wait(self: &mut SelfMetrics, wait: f64) {
+        self.imds_lock_waits += 1;
+        self.imds_lock_wait_seconds_sum += wait;
+    }
+
+    pub fn record_worker_iteration(self: &mut SelfMetrics, duration: f64) {
+        self.worker_iterations += 1;
+        self.worker_duration_seconds_sum += duration;
+        self.worker_last_duration_seconds = duration;
+    }
+
+    pub fn record_weathermap_cache(self: &mut SelfMetrics, hit: bool) {
+        if hit { self.weathermap_cache_hits += 1; } else { self.weathermap_cache_misses += 1; }
+    }
+
+    pub fn get_metrics(self: &SelfMetrics) -> Vec<models::metrics::LabeledMetric> {
+        let jaspy_nexus_http_requests = "jaspy_nexus_http_requests".to_string();
+        let jaspy_nexus_http_request_seconds_sum = "jaspy_nexus_http_request_seconds_sum".to_string();
+        let no_labels: HashMap<String,String> = HashMap::new();
+
+        let mut metric_values: Vec<models::metrics::LabeledMetric> = Vec::new();
+        for (&(ref method, ref route, status), route_request_counters) in self.route_requests.iter() {
+            let mut labels: HashMap<String,String> = HashMap::new();
+            labels.insert("method".to_string(), method.clone());
+            labels.insert("route".to_string(), route.clone());
+            labels.insert("status".to_string(), status.to_string());
+            metric_values.push(models::metrics::LabeledMetric::new(
+                &jaspy_nexus_http_requests, models::metrics::MetricValue::Uint64(route_request_counters.requests),
+                &labels
+            ));
+            metric_values.push(models::metrics::LabeledMetric::new(
+                &jaspy_nexus_http_request_seconds_sum, models::metrics::MetricValue::Float64(route_request_counters.latency_seconds_sum),
+                &labels
+            ));
+        }
+
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_imds_lock_waits".to_string(), models::metrics::MetricValue::Uint64(self.imds_lock_waits),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_imds_lock_wait_seconds_sum".to_string(), models::metrics::MetricValue::Float64(self.imds_lock_wait_seconds_sum),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_worker_iterations".to_string(), models::metrics::MetricValue::Uint64(self.worker_iterations),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_worker_duration_seconds_sum".to_string(), models::metrics::MetricValue::Float64(self.worker_duration_seconds_sum),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_worker_last_duration_seconds".to_string(), models::metrics::MetricValue::Float64(self.worker_last_duration_seconds),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_weathermap_cache_hits".to_string(), models::metrics::MetricValue::Uint64(self.weathermap_cache_hits),
+            &no_labels
+        ));
+        metric_values.push(models::metrics::LabeledMetric::new(
+            &"jaspy_nexus_weathermap_cache_misses".to_string(), models::metrics::MetricValue::Uint64(self.weathermap_cache_misses),
+            &no_labels
+        ));
+
+        return metric_values;
+    }
+}
+
+struct RequestStartTime(f64);
+
+pub struct RequestMetricsFairing {
+    pub self_metrics: Arc<Mutex<SelfMetrics>>,
+}
+
+impl Fairing for RequestMetricsFairing {
+    fn info(&self) -> Info {
+        return Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        };
+    }
+
+    fn on_request(&self, request: &mut Request, _data: &Data) {
+        request.local_cache(|| RequestStartTime(tools::get_time()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let started = request.local_cache(|| RequestStartTime(tools::get_time())).0;
+        // Label by route template instead of the raw URI to keep the label set bounded
+        let route = match request.route() {
+            Some(route) => route.uri.path().to_string(),
+            None => "unmatched".to_string(),
+        };
+        if let Ok(ref mut self_metrics) = self.self_metrics.lock() {
+            self_metrics.record_request(request.method().as_str(), &route, response.status().code, tools::get_time() - started);
+        }
+    }
+}
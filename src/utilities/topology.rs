@@ -0,0 +1,527 @@
+use models;
+use diesel;
+use diesel::pg::PgConnection;
+use serde_json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use utilities;
+
+#[derive(Clone)]
+pub struct TopologyLink {
+    pub local_device: i32,
+    pub local_interface: i32,
+    pub remote_device: i32,
+    pub remote_interface: i32,
+}
+
+pub struct TopologyRoots {
+    pub fqdns: Vec<String>,
+}
+
+impl TopologyRoots {
+    pub fn new(fqdns: &Vec<String>) -> TopologyRoots {
+        return TopologyRoots {
+            fqdns: fqdns.clone(),
+        };
+    }
+}
+
+pub struct TopologyCutAnalysis {
+    pub articulation_points: Vec<i32>,
+    pub bridges: Vec<TopologyLink>,
+}
+
+struct LowLinkState {
+    counter: u32,
+    index: HashMap<i32, u32>,
+    low: HashMap<i32, u32>,
+    articulation_points: HashSet<i32>,
+    bridges: Vec<TopologyLink>,
+}
+
+pub struct TopologyGraph {
+    pub devices: HashMap<i32, models::dbo::Device>,
+    pub interfaces: HashMap<i32, models::dbo::Interface>,
+    pub links: HashMap<i32, Vec<TopologyLink>>,
+    device_ids_by_fqdn: HashMap<String, i32>,
+    interface_ids_by_index: HashMap<(i32, i32), i32>,
+    interface_ids_by_device: HashMap<i32, Vec<i32>>,
+}
+
+impl TopologyGraph {
+    pub fn empty() -> TopologyGraph {
+        return TopologyGraph {
+            devices: HashMap::new(),
+            interfaces: HashMap::new(),
+            links: HashMap::new(),
+            device_ids_by_fqdn: HashMap::new(),
+            interface_ids_by_index: HashMap::new(),
+            interface_ids_by_device: HashMap::new(),
+        };
+    }
+
+    pub fn load(connection: &PgConnection) -> TopologyGraph {
+        return TopologyGraph::from_rows(models::dbo::Device::all(connection), models::dbo::Interface::all(connection));
+    }
+
+    pub fn from_rows(device_rows: Vec<models::dbo::Device>, interface_rows: Vec<models::dbo::Interface>) -> TopologyGraph {
+        let mut devices : HashMap<i32, models::dbo::Device> = HashMap::new();
+        let mut interfaces : HashMap<i32, models::dbo::Interface> = HashMap::new();
+        let mut links : HashMap<i32, Vec<TopologyLink>> = HashMap::new();
+        let mut device_ids_by_fqdn : HashMap<String, i32> = HashMap::new();
+        let mut interface_ids_by_index : HashMap<(i32, i32), i32> = HashMap::new();
+        let mut interface_ids_by_device : HashMap<i32, Vec<i32>> = HashMap::new();
+
+        for device in device_rows {
+            device_ids_by_fqdn.insert(format!("{}.{}", device.name, device.dns_domain), device.id);
+            links.insert(device.id, Vec::new());
+            devices.insert(device.id, device);
+        }
+        for interface in interface_rows {
+            interface_ids_by_index.insert((interface.device_id, interface.index), interface.id);
+            interface_ids_by_device.entry(interface.device_id).or_insert_with(Vec::new).push(interface.id);
+            interfaces.insert(interface.id, interface);
+        }
+
+        // Links are usually only stored on one side, the graph is undirected
+        let mut seen_links : HashSet<(i32, i32)> = HashSet::new();
+        for interface in interfaces.values() {
+            // Same precedence as Interface::peer_interface
+            let peer_interface_id = match interface.virtual_connection {
+                Some(peer_interface_id) => peer_interface_id,
+                None => match interface.connected_interface {
+                    Some(peer_interface_id) => peer_interface_id,
+                    None => { continue; }
+                }
+            };
+            let peer_interface = match interfaces.get(&peer_interface_id) {
+                Some(peer_interface) => peer_interface,
+                None => { continue; }
+            };
+            if peer_interface.device_id == interface.device_id { continue; }
+            let link_key = if interface.id < peer_interface.id { (interface.id, peer_interface.id) } else { (peer_interface.id, interface.id) };
+            if seen_links.contains(&link_key) { continue; }
+            seen_links.insert(link_key);
+
+            if let Some(device_links) = links.get_mut(&interface.device_id) {
+                device_links.push(TopologyLink {
+                    local_device: interface.device_id,
+                    local_interface: interface.id,
+                    remote_device: peer_interface.device_id,
+                    remote_interface: peer_interface.id,
+                });
+            }
+            if let Some(device_links) = links.get_mut(&peer_interface.device_id) {
+                device_links.push(TopologyLink {
+                    local_device: peer_interface.device_id,
+                    local_interface: peer_interface.id,
+                    remote_device: interface.device_id,
+                    remote_interface: interface.id,
+                });
+            }
+        }
+        for device_links in links.values_mut() {
+            device_links.sort_by_key(|link| link.local_interface);
+        }
+
+        return TopologyGraph {
+            devices: devices,
+            interfaces: interfaces,
+            links: links,
+            device_ids_by_fqdn: device_ids_by_fqdn,
+            interface_ids_by_index: interface_ids_by_index,
+            interface_ids_by_device: interface_ids_by_device,
+        };
+    }
+
+    pub fn device_id_by_fqdn(self: &TopologyGraph, fqdn: &String) -> Option<i32> {
+        return self.device_ids_by_fqdn.get(fqdn).cloned();
+    }
+
+    pub fn interface_by_index(self: &TopologyGraph, device_id: i32, if_index: i32) -> Option<&models::dbo::Interface> {
+        match self.interface_ids_by_index.get(&(device_id, if_index)) {
+            Some(interface_id) => {
+                return self.interfaces.get(interface_id);
+            },
+            None => {
+                return None;
+            }
+        }
+    }
+
+    pub fn device_interfaces(self: &TopologyGraph, device_id: i32) -> Vec<&models::dbo::Interface> {
+        let mut device_interfaces : Vec<&models::dbo::Interface> = Vec::new();
+        if let Some(interface_ids) = self.interface_ids_by_device.get(&device_id) {
+            for interface_id in interface_ids.iter() {
+                if let Some(interface) = self.interfaces.get(interface_id) {
+                    device_interfaces.push(interface);
+                }
+            }
+        }
+        device_interfaces.sort_by_key(|interface| interface.index);
+        return device_interfaces;
+    }
+
+    // Directional like Interface::peer_interface, only follows the link stored on this interface
+    pub fn peer_interface(self: &TopologyGraph, interface_id: i32) -> Option<&models::dbo::Interface> {
+        let interface = self.interfaces.get(&interface_id)?;
+        if let Some(peer_interface_id) = interface.virtual_connection {
+            return self.interfaces.get(&peer_interface_id);
+        } else if let Some(peer_interface_id) = interface.connected_interface {
+            return self.interfaces.get(&peer_interface_id);
+        } else {
+            return None;
+        }
+    }
+
+    pub fn fqdn(self: &TopologyGraph, device_id: i32) -> Option<String> {
+        match self.devices.get(&device_id) {
+            Some(device) => {
+                return Some(format!("{}.{}", device.name, device.dns_domain));
+            },
+            None => {
+                return None;
+            }
+        }
+    }
+
+    pub fn device_links(self: &TopologyGraph, device_id: i32) -> &[TopologyLink] {
+        match self.links.get(&device_id) {
+            Some(device_links) => {
+                return device_links;
+            },
+            None => {
+                return &[];
+            }
+        }
+    }
+
+    pub fn shortest_path(self: &TopologyGraph, from_device: i32, to_device: i32) -> Option<Vec<TopologyLink>> {
+        if !self.devices.contains_key(&from_device) || !self.devices.contains_key(&to_device) {
+            return None;
+        }
+
+        let mut arrived_via : HashMap<i32, TopologyLink> = HashMap::new();
+        let mut visited : HashSet<i32> = HashSet::new();
+        let mut queue : VecDeque<i32> = VecDeque::new();
+        visited.insert(from_device);
+        queue.push_back(from_device);
+        while let Some(device_id) = queue.pop_front() {
+            if device_id == to_device { break; }
+            for link in self.device_links(device_id).iter() {
+                if visited.contains(&link.remote_device) { continue; }
+                visited.insert(link.remote_device);
+                arrived_via.insert(link.remote_device, link.clone());
+                queue.push_back(link.remote_device);
+            }
+        }
+
+        if !visited.contains(&to_device) {
+            return None;
+        }
+
+        let mut path : Vec<TopologyLink> = Vec::new();
+        let mut device_id = to_device;
+        while device_id != from_device {
+            let link = arrived_via[&device_id].clone();
+            device_id = link.local_device;
+            path.push(link);
+        }
+        path.reverse();
+        return Some(path);
+    }
+
+    fn sorted_device_ids(self: &TopologyGraph) -> Vec<i32> {
+        let mut device_ids : Vec<i32> = self.devices.keys().cloned().collect();
+        device_ids.sort();
+        return device_ids;
+    }
+
+    // Tarjan's low-link walk. The graph is a multigraph, so only the exact link we
+    // arrived through is skipped, parallel links between two devices still count.
+    fn visit_low_link(self: &TopologyGraph, device_id: i32, arrived_via: Option<i32>, state: &mut LowLinkState) {
+        state.counter += 1;
+        let device_index = state.counter;
+        state.index.insert(device_id, device_index);
+        state.low.insert(device_id, device_index);
+        let mut children = 0;
+        for link in self.device_links(device_id).iter() {
+            if arrived_via == Some(link.local_interface) { continue; }
+            let remote_index = state.index.get(&link.remote_device).cloned();
+            match remote_index {
+                Some(remote_index) => {
+                    if remote_index < state.low[&device_id] { state.low.insert(device_id, remote_index); }
+                },
+                None => {
+                    children += 1;
+                    self.visit_low_link(link.remote_device, Some(link.remote_interface), state);
+                    let remote_low = state.low[&link.remote_device];
+                    if remote_low < state.low[&device_id] { state.low.insert(device_id, remote_low); }
+                    if remote_low > device_index { state.bridges.push(link.clone()); }
+                    if arrived_via.is_some() && remote_low >= device_index { state.articulation_points.insert(device_id); }
+                }
+            }
+        }
+        if arrived_via.is_none() && children > 1 {
+            state.articulation_points.insert(device_id);
+        }
+    }
+
+    pub fn cut_analysis(self: &TopologyGraph) -> TopologyCutAnalysis {
+        let mut state = LowLinkState {
+            counter: 0,
+            index: HashMap::new(),
+            low: HashMap::new(),
+            articulation_points: HashSet::new(),
+            bridges: Vec::new(),
+        };
+        for device_id in self.sorted_device_ids().iter() {
+            if state.index.contains_key(device_id) { continue; }
+            self.visit_low_link(*device_id, None, &mut state);
+        }
+        let mut articulation_points : Vec<i32> = state.articulation_points.into_iter().collect();
+        articulation_points.sort();
+        return TopologyCutAnalysis {
+            articulation_points: articulation_points,
+            bridges: state.bridges,
+        };
+    }
+
+    // Hop distances from the closest root, optionally pretending a device or a link
+    // (identified by either of its interfaces) is gone
+    pub fn distances_from(self: &TopologyGraph, roots: &Vec<i32>, removed_device: Option<i32>, removed_interface: Option<i32>) -> HashMap<i32, u32> {
+        let mut distances : HashMap<i32, u32> = HashMap::new();
+        let mut queue : VecDeque<i32> = VecDeque::new();
+        for root in roots.iter() {
+            if Some(*root) == removed_device || !self.devices.contains_key(root) { continue; }
+            if distances.contains_key(root) { continue; }
+            distances.insert(*root, 0);
+            queue.push_back(*root);
+        }
+        while let Some(device_id) = queue.pop_front() {
+            let distance = distances[&device_id];
+            for link in self.device_links(device_id).iter() {
+                if Some(link.remote_device) == removed_device { continue; }
+                if Some(link.local_interface) == removed_interface || Some(link.remote_interface) == removed_interface { continue; }
+                if distances.contains_key(&link.remote_device) { continue; }
+                distances.insert(link.remote_device, distance + 1);
+                queue.push_back(link.remote_device);
+            }
+        }
+        return distances;
+    }
+
+    pub fn single_uplink_devices(self: &TopologyGraph, roots: &Vec<i32>) -> Vec<TopologyLink> {
+        let distances = self.distances_from(roots, None, None);
+        let mut single_uplinks : Vec<TopologyLink> = Vec::new();
+        for device_id in self.sorted_device_ids().iter() {
+            let distance = match distances.get(device_id) {
+                Some(distance) if *distance > 0 => *distance,
+                _ => { continue; }
+            };
+            let uplinks : Vec<&TopologyLink> = self.device_links(*device_id).iter()
+                .filter(|link| distances.get(&link.remote_device) == Some(&(distance - 1)))
+                .collect();
+            if uplinks.len() == 1 {
+                single_uplinks.push(uplinks[0].clone());
+            }
+        }
+        return single_uplinks;
+    }
+
+    // Sorted and with links in a canonical direction so equal topologies serialize equally
+    pub fn snapshot_data(self: &TopologyGraph) -> models::json::TopologySnapshotData {
+        let mut snapshot_data = models::json::TopologySnapshotData {
+            devices: Vec::new(),
+            interfaces: Vec::new(),
+            links: Vec::new(),
+        };
+        for device_id in self.sorted_device_ids().iter() {
+            let fqdn = match self.fqdn(*device_id) {
+                Some(fqdn) => fqdn,
+                None => { continue; }
+            };
+            for link in self.device_links(*device_id).iter() {
+                let local_interface = &self.interfaces[&link.local_interface];
+                let remote_interface = &self.interfaces[&link.remote_interface];
+                let remote_fqdn = match self.fqdn(link.remote_device) {
+                    Some(remote_fqdn) => remote_fqdn,
+                    None => { continue; }
+                };
+                if (&fqdn, &local_interface.name) > (&remote_fqdn, &remote_interface.name) { continue; }
+                snapshot_data.links.push(models::json::TopologyLinkInfo {
+                    fqdn: fqdn.clone(),
+                    interface: local_interface.name.clone(),
+                    peer_fqdn: remote_fqdn,
+                    peer_interface: remote_interface.name.clone(),
+                });
+            }
+            snapshot_data.devices.push(fqdn);
+        }
+        for interface in self.interfaces.values() {
+            if let Some(fqdn) = self.fqdn(interface.device_id) {
+                snapshot_data.interfaces.push(models::json::TopologySnapshotInterface {
+                    fqdn: fqdn,
+                    name: interface.name.clone(),
+                });
+            }
+        }
+        snapshot_data.devices.sort();
+        snapshot_data.interfaces.sort();
+        snapshot_data.links.sort();
+        return snapshot_data;
+    }
+}
+
+pub fn take_snapshot(connection: &PgConnection, reason: &str, skip_unchanged: bool) -> Result<Option<models::dbo::TopologySnapshot>, diesel::result::Error> {
+    let snapshot_data = TopologyGraph::load(connection).snapshot_data();
+    let data = format!("{}", json!(snapshot_data));
+    if skip_unchanged {
+        if let Some(latest_snapshot) = models::dbo::TopologySnapshot::latest(connection) {
+            if latest_snapshot.data == data { return Ok(None); }
+        }
+    }
+    let new_topology_snapshot = models::dbo::NewTopologySnapshot {
+        created_at: utilities::tools::get_time(),
+        reason: reason.to_string(),
+        data: data,
+    };
+    return models::dbo::TopologySnapshot::create(&new_topology_snapshot, connection).map(Some);
+}
+
+pub fn snapshot_data(topology_snapshot: &models::dbo::TopologySnapshot) -> Option<models::json::TopologySnapshotData> {
+    match serde_json::from_str::<models::json::TopologySnapshotData>(&topology_snapshot.data) {
+        Ok(snapshot_data) => {
+            return Some(snapshot_data);
+        },
+        Err(error) => {
+            log_error!("stored topology snapshot is corrupt", "snapshot_id" => topology_snapshot.id, "error" => error);
+            return None;
+        }
+    }
+}
+
+fn added_and_removed<T: Clone + Eq + Hash + Ord>(old: &Vec<T>, new: &Vec<T>) -> (Vec<T>, Vec<T>) {
+    let old_set : HashSet<&T> = old.iter().collect();
+    let new_set : HashSet<&T> = new.iter().collect();
+    let mut added : Vec<T> = new.iter().filter(|item| !old_set.contains(item)).cloned().collect();
+    let mut removed : Vec<T> = old.iter().filter(|item| !new_set.contains(item)).cloned().collect();
+    added.sort();
+    removed.sort();
+    return (added, removed);
+}
+
+pub fn snapshot_diff(from: i32, from_data: &models::json::TopologySnapshotData, to: Option<i32>, to_data: &models::json::TopologySnapshotData) -> models::json::TopologySnapshotDiff {
+    let (added_devices, removed_devices) = added_and_removed(&from_data.devices, &to_data.devices);
+    let (added_interfaces, removed_interfaces) = added_and_removed(&from_data.interfaces, &to_data.interfaces);
+    let (added_links, removed_links) = added_and_removed(&from_data.links, &to_data.links);
+    return models::json::TopologySnapshotDiff {
+        from: from,
+        to: to,
+        added_devices: added_devices,
+        removed_devices: removed_devices,
+        added_interfaces: added_interfaces,
+        removed_interfaces: removed_interfaces,
+        added_links: added_links,
+        removed_links: removed_links,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Devices 1..=device_count, every link gets its own pair of interfaces and is stored on one side only
+    fn graph(device_count: i32, links: &[(i32, i32)]) -> TopologyGraph {
+        let devices : Vec<models::dbo::Device> = (1..device_count + 1).map(|device_id| models::dbo::Device {
+            id: device_id,
+            name: format!("switch{}", device_id),
+            dns_domain: "example.net".to_string(),
+            base_mac: None,
+            polling_enabled: None,
+            os_info: None,
+            credential_profile_id: None,
+        }).collect();
+        let mut interfaces : Vec<models::dbo::Interface> = Vec::new();
+        for (position, &(local_device, remote_device)) in links.iter().enumerate() {
+            let local_interface = 2 * position as i32 + 1;
+            interfaces.push(interface(local_interface, local_device, Some(local_interface + 1)));
+            interfaces.push(interface(local_interface + 1, remote_device, None));
+        }
+        return TopologyGraph::from_rows(devices, interfaces);
+    }
+
+    fn interface(id: i32, device_id: i32, connected_interface: Option<i32>) -> models::dbo::Interface {
+        return models::dbo::Interface {
+            id: id,
+            index: id,
+            interface_type: "ethernetCsmacd".to_string(),
+            connected_interface: connected_interface,
+            device_id: device_id,
+            display_name: None,
+            name: format!("eth{}", id),
+            alias: None,
+            description: None,
+            polling_enabled: None,
+            speed_override: None,
+            virtual_connection: None,
+            aggregate_interface: None,
+        };
+    }
+
+    fn device_pairs(links: &Vec<TopologyLink>) -> Vec<(i32, i32)> {
+        let mut pairs : Vec<(i32, i32)> = links.iter().map(|link| (link.local_device.min(link.remote_device), link.local_device.max(link.remote_device))).collect();
+        pairs.sort();
+        return pairs;
+    }
+
+    #[test]
+    fn chain_is_all_cut_vertices_and_bridges() {
+        let topology_graph = graph(4, &[(1, 2), (2, 3), (3, 4)]);
+        let cut_analysis = topology_graph.cut_analysis();
+        assert_eq!(cut_analysis.articulation_points, vec![2, 3]);
+        assert_eq!(device_pairs(&cut_analysis.bridges), vec![(1, 2), (2, 3), (3, 4)]);
+    }
+
+    #[test]
+    fn parallel_links_are_not_bridges() {
+        let topology_graph = graph(3, &[(1, 2), (2, 1), (2, 3)]);
+        let cut_analysis = topology_graph.cut_analysis();
+        assert_eq!(cut_analysis.articulation_points, vec![2]);
+        assert_eq!(device_pairs(&cut_analysis.bridges), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn ring_has_no_single_point_of_failure() {
+        let topology_graph = graph(4, &[(1, 2), (2, 3), (3, 4), (4, 1)]);
+        let cut_analysis = topology_graph.cut_analysis();
+        assert!(cut_analysis.articulation_points.is_empty());
+        assert!(cut_analysis.bridges.is_empty());
+    }
+
+    #[test]
+    fn distances_skip_removed_devices_and_links() {
+        let topology_graph = graph(4, &[(1, 2), (2, 3), (3, 4)]);
+        let roots = vec![1];
+        let distances = topology_graph.distances_from(&roots, None, None);
+        assert_eq!(distances.len(), 4);
+        assert_eq!(distances[&4], 3);
+        let distances = topology_graph.distances_from(&roots, Some(2), None);
+        assert_eq!(distances.keys().cloned().collect::<Vec<i32>>(), vec![1]);
+        // Interface 4 is the far side of the link between devices 2 and 3
+        let mut reachable : Vec<i32> = topology_graph.distances_from(&roots, None, Some(4)).keys().cloned().collect();
+        reachable.sort();
+        assert_eq!(reachable, vec![1, 2]);
+    }
+
+    #[test]
+    fn single_uplinks_ignore_redundant_devices() {
+        let roots = vec![1];
+        let chain = graph(4, &[(1, 2), (2, 3), (3, 4)]);
+        assert_eq!(chain.single_uplink_devices(&roots).iter().map(|link| link.local_device).collect::<Vec<i32>>(), vec![2, 3, 4]);
+        let ring = graph(4, &[(1, 2), (2, 3), (3, 4), (4, 1)]);
+        assert_eq!(ring.single_uplink_devices(&roots).iter().map(|link| link.local_device).collect::<Vec<i32>>(), vec![2, 4]);
+        let parallel = graph(3, &[(1, 2), (2, 1), (2, 3)]);
+        assert_eq!(parallel.single_uplink_devices(&roots).iter().map(|link| link.local_device).collect::<Vec<i32>>(), vec![3]);
+    }
+}